@@ -0,0 +1,374 @@
+"""Client for the Hetzner DNS API"""
+
+import logging
+import re
+import time
+from collections.abc import Callable, Iterator, Sequence
+from dataclasses import dataclass
+from datetime import datetime
+from enum import StrEnum
+from functools import reduce
+from typing import Protocol
+
+import requests
+from requests import PreparedRequest
+from requests.adapters import HTTPAdapter
+from requests.auth import AuthBase
+from urllib3.util import Retry, Timeout
+
+__version__ = "1.0.0"
+
+DEFAULT_URL = "https://dns.hetzner.com/api/v1"
+DEFAULT_TIMEOUT = Timeout(connect=10, total=30)
+ERROR_BODY_LIMIT = 200
+
+logger = logging.getLogger(__name__)
+
+# sends a request given the method, the path below the api url and keyword arguments of `requests.request`
+Send = Callable[..., requests.Response]
+# wraps the next step of sending a request, e.g. to retry or trace it
+Middleware = Callable[[Send], Send]
+
+
+class InvalidResponseError(requests.RequestException):
+    """The api answered with a body which is not valid json"""
+
+
+class RecordType(StrEnum):
+    A = "A"
+    AAAA = "AAAA"
+    CAA = "CAA"
+    CNAME = "CNAME"
+    DANE = "DANE"
+    DS = "DS"
+    HINFO = "HINFO"
+    MX = "MX"
+    NS = "NS"
+    RP = "RP"
+    SOA = "SOA"
+    SRV = "SRV"
+    TLSA = "TLSA"
+    TXT = "TXT"
+
+    @classmethod
+    def parse(cls, value: str) -> "RecordType | str":
+        """The matching `RecordType` or the string itself for types which are unknown to this client"""
+        try:
+            return cls(value)
+        except ValueError:
+            return value
+
+
+class ZoneStatus(StrEnum):
+    VERIFIED = "verified"
+    FAILED = "failed"
+    PENDING = "pending"
+
+    @classmethod
+    def parse(cls, value: str) -> "ZoneStatus | str":
+        """The matching `ZoneStatus` or the string itself for states which are unknown to this client"""
+        try:
+            return cls(value)
+        except ValueError:
+            return value
+
+
+class RecordAction(StrEnum):
+    """What `Client.ensure_record()` had to do"""
+
+    CREATED = "created"
+    UPDATED = "updated"
+    UNCHANGED = "unchanged"
+
+
+TIMESTAMP_PATTERN = re.compile(r"(\d{4}-\d\d-\d\d)[ T](\d\d:\d\d:\d\d)(?:\.(\d+))? ?([+-]\d\d:?\d\d|Z)")
+
+
+def parse_timestamp(value: str) -> datetime:
+    """Parse timestamps like the `created` and `modified` fields of zones and records into aware datetimes
+
+    The api formats them like `2019-08-24 11:31:41.442 +0000 UTC` with a varying number of fractional digits.
+    """
+    match = TIMESTAMP_PATTERN.match(value)
+    if not match:
+        raise ValueError(f"Invalid timestamp {value!r}")
+    date, time_of_day, fraction, offset = match.groups()
+    fraction = (fraction or "0")[:6].ljust(6, "0")
+    return datetime.fromisoformat(f"{date}T{time_of_day}.{fraction}{offset.replace('Z', '+00:00')}")
+
+
+def parse_timestamps(item: dict) -> dict:
+    """Turn the `created` and `modified` fields into datetimes, keeping values which can't be parsed as they are"""
+    result = dict(item)
+    for key in ("created", "modified"):
+        if isinstance(result.get(key), str):
+            try:
+                result[key] = parse_timestamp(result[key])
+            except ValueError:
+                pass
+    return result
+
+
+def parse_zone(zone: dict) -> dict:
+    zone = parse_timestamps(zone)
+    if "status" in zone:
+        zone["status"] = ZoneStatus.parse(zone["status"])
+    return zone
+
+
+def parse_record(record: dict) -> dict:
+    return {**parse_timestamps(record), "type": RecordType.parse(record["type"])}
+
+
+@dataclass
+class Page:
+    """One page of a list endpoint together with the pagination info of the api"""
+
+    items: list[dict]
+    page: int = 1
+    per_page: int | None = None
+    last_page: int = 1
+    total_entries: int | None = None
+
+    def has_next_page(self) -> bool:
+        return self.page < self.last_page
+
+    def next_page(self) -> int | None:
+        return self.page + 1 if self.has_next_page() else None
+
+    def total_pages(self) -> int:
+        return self.last_page
+
+
+class DnsApi(Protocol):
+    """The part of the api which is needed to keep records up to date and create missing ones
+
+    Code depending only on this instead of `Client` can be tested against in-memory fakes.
+    """
+
+    def get_zone_by_name(self, zone_name: str) -> dict: ...
+
+    def get_records(self, zone_id: str) -> list[dict]: ...
+
+    def update_record(self, record_id: str, data: dict) -> dict: ...
+
+    def create_record(self, data: dict) -> dict: ...
+
+
+class HetznerAuth(AuthBase):
+    def __init__(self, api_token: str):
+        self.api_token = api_token
+
+    def __call__(self, r: PreparedRequest) -> PreparedRequest:
+        r.headers["Auth-API-Token"] = self.api_token
+        return r
+
+
+class Client:
+    def __init__(
+        self,
+        api_token: str,
+        session: requests.Session | None = None,
+        *,
+        url: str | None = None,
+        timeout: float | Timeout | None = DEFAULT_TIMEOUT,
+        user_agent: str | None = None,
+        proxies: dict[str, str] | None = None,
+        retries: int | Retry = 0,
+        middleware: Sequence[Middleware] = (),
+    ):
+        """Create a client using the given api token
+
+        `session` can be used to send requests differently, e.g. through a custom transport adapter.
+        `timeout` applies to every request and is either a number of seconds for connecting and each read or a
+        `urllib3.util.Timeout` (by default 10 seconds for connecting and 30 seconds in total).
+        `proxies` maps url schemes to proxy urls like in requests and `retries` is either the number of retries for
+        failed connections or a `urllib3.util.Retry` policy.
+        `middleware` is applied to every request with the first one being the outermost.
+        """
+        self.url = (url or DEFAULT_URL).rstrip("/")
+        self.timeout = timeout
+        self.sess = session or requests.Session()
+        self.sess.auth = HetznerAuth(api_token)
+        self.sess.headers["User-Agent"] = user_agent or f"hetzner_dns_api/{__version__}"
+        self.proxies = proxies or {}
+        if retries:
+            self.sess.mount(self.url, HTTPAdapter(max_retries=retries))
+        self.send_chain: Send = reduce(lambda send, m: m(send), reversed(middleware), self.send_direct)
+
+    def send_direct(self, method: str, path: str, **kwargs) -> requests.Response:
+        kwargs.setdefault("timeout", self.timeout)
+        if self.proxies:
+            # passed with each request since requests would otherwise prefer proxies from the environment
+            kwargs.setdefault("proxies", self.proxies)
+        return self.sess.request(method, f"{self.url}{path}", **kwargs)
+
+    def send(self, method: str, path: str, **kwargs) -> requests.Response:
+        """Send a request to the api through the middleware without checking the response"""
+        return self.send_chain(method, path, **kwargs)
+
+    def request(self, method: str, path: str, **kwargs) -> dict:
+        resp = self.send(method, path, **kwargs)
+        resp.raise_for_status()
+        # e.g. deletions are answered without a body
+        if not resp.content:
+            return {}
+        try:
+            return resp.json()
+        except ValueError:
+            logger.debug("Invalid json in response to %s %s: %s", method, path, resp.text)
+            snippet = resp.text if len(resp.text) <= ERROR_BODY_LIMIT else resp.text[:ERROR_BODY_LIMIT] + "..."
+            msg = f"Invalid json in response with status {resp.status_code} to {method} {path}: {snippet!r}"
+            raise InvalidResponseError(msg, response=resp) from None
+
+    def get_page(self, path: str, key: str, page: int = 1, params: dict | None = None) -> Page:
+        """Get one page of the objects listed under `key` by a list endpoint"""
+        data = self.request("GET", path, params={**(params or {}), "page": page})
+        # endpoints which are not paginated list everything at once
+        pagination = data.get("meta", {}).get("pagination", {"page": page, "last_page": page})
+        return Page(
+            data[key],
+            pagination["page"],
+            pagination.get("per_page"),
+            pagination["last_page"],
+            pagination.get("total_entries"),
+        )
+
+    def iter_pages(self, path: str, key: str, params: dict | None = None) -> Iterator[dict]:
+        """Iterate over the objects listed under `key` on all pages of a list endpoint"""
+        page = self.get_page(path, key, 1, params)
+        yield from page.items
+        while page.has_next_page():
+            page = self.get_page(path, key, page.next_page(), params)
+            yield from page.items
+
+    @staticmethod
+    def zone_params(per_page: int | None, name: str | None, search_name: str | None) -> dict:
+        if name is not None and search_name is not None:
+            raise ValueError("Zones can only be filtered by either name or search_name")
+        params = {"per_page": per_page, "name": name, "search_name": search_name}
+        return {k: v for k, v in params.items() if v is not None}
+
+    def get_zones(
+        self, page: int = 1, per_page: int | None = None, *, name: str | None = None, search_name: str | None = None
+    ) -> Page:
+        """Get one page of zones, optionally filtered by their exact `name` or a part of it (`search_name`)"""
+        result = self.get_page("/zones", "zones", page, self.zone_params(per_page, name, search_name))
+        result.items = [parse_zone(i) for i in result.items]
+        return result
+
+    def iter_zones(
+        self, per_page: int | None = None, *, name: str | None = None, search_name: str | None = None
+    ) -> Iterator[dict]:
+        params = self.zone_params(per_page, name, search_name)
+        return map(parse_zone, self.iter_pages("/zones", "zones", params))
+
+    def iter_records(self, zone_id: str, per_page: int | None = None) -> Iterator[dict]:
+        params = {"zone_id": zone_id, "per_page": per_page} if per_page else {"zone_id": zone_id}
+        return map(parse_record, self.iter_pages("/records", "records", params))
+
+    def get_zone(self, zone_id: str) -> dict:
+        return parse_zone(self.request("GET", f"/zones/{zone_id}")["zone"])
+
+    def get_zone_by_name(self, zone_name: str) -> dict:
+        return parse_zone(self.request("GET", "/zones", params={"name": zone_name})["zones"][0])
+
+    def create_zone(self, name: str, ttl: int | None = None) -> dict:
+        data = {"name": name} if ttl is None else {"name": name, "ttl": ttl}
+        return parse_zone(self.request("POST", "/zones", json=data)["zone"])
+
+    def delete_zone(self, zone_id: str):
+        self.request("DELETE", f"/zones/{zone_id}")
+
+    def validate_zone_file(self, zone_file: str) -> dict:
+        """Check a zone file in BIND format without importing it
+
+        Returns the number of `parsed_records` and the `valid_records`, invalid files are answered with an error.
+        """
+        return self.request(
+            "POST", "/zones/file/validate", data=zone_file.encode(), headers={"Content-Type": "text/plain"}
+        )
+
+    def get_primary_servers(self, zone_id: str) -> list[dict]:
+        """The primary servers from which a zone with `is_secondary_dns` is transferred"""
+        return self.request("GET", "/primary_servers", params={"zone_id": zone_id})["primary_servers"]
+
+    def create_primary_server(self, zone_id: str, address: str, port: int = 53) -> dict:
+        data = {"zone_id": zone_id, "address": address, "port": port}
+        return self.request("POST", "/primary_servers", json=data)["primary_server"]
+
+    def update_primary_server(self, primary_server_id: str, data: dict) -> dict:
+        return self.request("PUT", f"/primary_servers/{primary_server_id}", json=data)["primary_server"]
+
+    def delete_primary_server(self, primary_server_id: str):
+        self.request("DELETE", f"/primary_servers/{primary_server_id}")
+
+    def get_records(self, zone_id: str, per_page: int | None = None) -> list[dict]:
+        return list(self.iter_records(zone_id, per_page))
+
+    def get_record(self, record_id: str) -> dict:
+        return parse_record(self.request("GET", f"/records/{record_id}")["record"])
+
+    def create_record(self, data: dict) -> dict:
+        return parse_record(self.request("POST", "/records", json=data)["record"])
+
+    def bulk_create_records(self, records: list[dict]) -> dict:
+        """Create many records with one request
+
+        Returns the api's response with the created `records`, the `valid_records` and the `invalid_records`.
+        """
+        return self.request("POST", "/records/bulk", json={"records": records})
+
+    def update_record(self, record_id: str, data: dict) -> dict:
+        return parse_record(self.request("PUT", f"/records/{record_id}", json=data)["record"])
+
+    def ensure_record(
+        self, zone_name: str, name: str, typ: RecordType | str, value: str, ttl: int | None = None
+    ) -> tuple[RecordAction, dict]:
+        """Make sure that a record with the given name and type has the value and ttl, creating it if necessary
+
+        If several records have the name and type, the first one is updated.
+        """
+        zone = self.get_zone_by_name(zone_name)
+        data = {"zone_id": zone["id"], "name": name, "type": typ, "value": value}
+        if ttl is not None:
+            data["ttl"] = ttl
+        for record in self.get_records(zone["id"]):
+            if record["name"] == name and record["type"] == typ:
+                if record["value"] == value and (ttl is None or record.get("ttl") == ttl):
+                    return RecordAction.UNCHANGED, record
+                return RecordAction.UPDATED, self.update_record(record["id"], data)
+        return RecordAction.CREATED, self.create_record(data)
+
+
+def retry(
+    attempts: int = 3,
+    backoff: float = 1.0,
+    statuses: Sequence[int] = (429, 500, 502, 503, 504),
+    methods: Sequence[str] = ("GET", "PUT", "DELETE"),
+) -> Middleware:
+    """Middleware which retries idempotent requests after connection errors and the given status codes
+
+    The delay doubles after each attempt starting at `backoff` seconds unless the api sends a `Retry-After` header.
+    """
+
+    def middleware(send: Send) -> Send:
+        def retrying_send(method: str, path: str, **kwargs) -> requests.Response:
+            for attempt in range(attempts):
+                last_attempt = attempt == attempts - 1 or method not in methods
+                try:
+                    resp = send(method, path, **kwargs)
+                except (requests.ConnectionError, requests.Timeout):
+                    if last_attempt:
+                        raise
+                    delay = backoff * 2**attempt
+                else:
+                    if last_attempt or resp.status_code not in statuses:
+                        return resp
+                    retry_after = resp.headers.get("Retry-After", "")
+                    delay = float(retry_after) if retry_after.isdigit() else backoff * 2**attempt
+                time.sleep(delay)
+
+        return retrying_send
+
+    return middleware
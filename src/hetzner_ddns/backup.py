@@ -0,0 +1,53 @@
+import json
+import subprocess
+from pathlib import Path
+
+from hetzner_ddns.providers import Providers
+
+BACKUP_FIELDS = ("id", "zone", "name", "type", "value", "ttl")
+
+
+def get_managed_records(providers: Providers, targets: list[dict]) -> list[dict]:
+    """Fetch every record which is matched by one of the given targets"""
+    result = []
+    for target in targets:
+        for i_record in providers.for_target(target).find_records(target["zone"], target["record"]):
+            i_record = {**i_record, "zone": target["zone"]}
+            result.append({k: i_record[k] for k in BACKUP_FIELDS if k in i_record})
+    return result
+
+
+def format_records(records: list[dict], fmt: str) -> str:
+    if fmt == "toml":
+        # string values are json-escaped which is also valid for toml basic strings, toml has no null though
+        return "\n".join(
+            "[[records]]\n" + "".join(f"{k} = {json.dumps(v)}\n" for k, v in i.items() if v is not None)
+            for i in records
+        )
+    return json.dumps({"records": records}, indent=2) + "\n"
+
+
+def write_backup(records: list[dict], path: Path):
+    fmt = "toml" if path.suffix == ".toml" else "json"
+    tmp_file = path.with_name(f"{path.name}.tmp")
+    with open(tmp_file, "w") as f:
+        f.write(format_records(records, fmt))
+    tmp_file.replace(path)
+
+
+def commit_snapshot(records: list[dict], repo: Path, message: str):
+    """Write the records into a git repository and commit them if anything changed"""
+    def git(*args, check=True):
+        return subprocess.run(
+            ["git", "-c", "user.name=hetzner_ddns", "-c", "user.email=hetzner_ddns@localhost", *args],
+            cwd=repo,
+            check=check,
+        )
+
+    repo.mkdir(parents=True, exist_ok=True)
+    if not (repo / ".git").exists():
+        git("init", "--quiet")
+    write_backup(records, repo / "records.toml")
+    git("add", "records.toml")
+    if git("diff", "--cached", "--quiet", check=False).returncode != 0:
+        git("commit", "--quiet", "-m", message)
@@ -0,0 +1,88 @@
+import json
+from datetime import timedelta
+from pathlib import Path
+
+import requests
+from requests.adapters import BaseAdapter
+from requests.structures import CaseInsensitiveDict
+from requests.utils import get_encoding_from_headers
+
+from hetzner_ddns.logs import redact
+
+
+class Cassette:
+    """Http interactions which are recorded to or replayed from a json file
+
+    Only the method, url and body of requests are recorded, so that cassettes don't contain credentials sent in headers.
+    Secrets in urls and bodies, like the token of DuckDNS, are redacted.
+    """
+
+    def __init__(self, path: Path, replay: bool):
+        self.path = path
+        self.replay = replay
+        self.interactions: list[dict] = json.loads(path.read_text()) if replay else []
+        self.used: set[int] = set()
+
+    def adapter(self, inner: BaseAdapter) -> BaseAdapter:
+        return ReplayAdapter(self) if self.replay else RecordingAdapter(self, inner)
+
+    def record(self, request: requests.PreparedRequest, resp: requests.Response):
+        body = request.body.decode(errors="replace") if isinstance(request.body, bytes) else request.body
+        self.interactions.append(
+            {
+                "method": request.method,
+                "url": redact(request.url),
+                "body": redact(body) if body is not None else None,
+                "status": resp.status_code,
+                "reason": resp.reason,
+                "headers": dict(resp.headers),
+                "response": resp.text,
+            }
+        )
+        self.path.write_text(json.dumps(self.interactions, indent=2))
+
+    def find(self, request: requests.PreparedRequest) -> dict:
+        """The first interaction with the method and url of the request which has not been replayed yet"""
+        url = redact(request.url)
+        for i, interaction in enumerate(self.interactions):
+            if i not in self.used and interaction["method"] == request.method and interaction["url"] == url:
+                self.used.add(i)
+                return interaction
+        raise requests.ConnectionError(f"No recorded response for {request.method} {url} in {self.path}")
+
+
+class RecordingAdapter(BaseAdapter):
+    def __init__(self, cassette: Cassette, inner: BaseAdapter):
+        super().__init__()
+        self.cassette = cassette
+        self.inner = inner
+
+    def send(self, request: requests.PreparedRequest, **kwargs) -> requests.Response:
+        resp = self.inner.send(request, **kwargs)
+        self.cassette.record(request, resp)
+        return resp
+
+    def close(self):
+        self.inner.close()
+
+
+class ReplayAdapter(BaseAdapter):
+    def __init__(self, cassette: Cassette):
+        super().__init__()
+        self.cassette = cassette
+
+    def send(self, request: requests.PreparedRequest, **kwargs) -> requests.Response:
+        interaction = self.cassette.find(request)
+        resp = requests.Response()
+        resp.request = request
+        resp.url = request.url
+        resp.status_code = interaction["status"]
+        resp.reason = interaction["reason"]
+        resp.headers = CaseInsensitiveDict(interaction["headers"])
+        resp.encoding = get_encoding_from_headers(resp.headers) or "utf-8"
+        resp._content = interaction["response"].encode(resp.encoding)
+        resp.elapsed = timedelta(0)
+        return resp
+
+    def close(self):
+        pass
@@ -0,0 +1,26 @@
+import sys
+import tomllib
+from pathlib import Path
+
+
+def load_config(path: Path, profile: str | None = None) -> dict:
+    with open(path, "rb") as f:
+        cfg = tomllib.load(f)
+
+    # settings of the selected profile override the top-level ones
+    profiles = cfg.pop("profiles", {})
+    if profile is not None:
+        if profile not in profiles:
+            sys.exit(f"Profile {profile} is not defined in {path}")
+        cfg.update(profiles[profile])
+    return cfg
+
+
+def parse_duration(value: int | float | str) -> float:
+    """Parse a duration given as number of seconds or as string with unit like `"90s"`, `"5m"`, `"1h"` or `"1d"`"""
+    if isinstance(value, (int, float)):
+        return value
+    units = {"s": 1, "m": 60, "h": 3600, "d": 86400}
+    if value and value[-1] in units:
+        return float(value[:-1]) * units[value[-1]]
+    return float(value)
@@ -0,0 +1,51 @@
+import http.client
+import json
+import socket
+
+LABEL_HOSTNAME = "hetzner-ddns.hostname"
+LABEL_ZONE = "hetzner-ddns.zone"
+
+
+class UnixHTTPConnection(http.client.HTTPConnection):
+    def __init__(self, socket_path: str):
+        super().__init__("localhost")
+        self.socket_path = socket_path
+
+    def connect(self):
+        self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        self.sock.connect(self.socket_path)
+
+
+def list_containers(socket_path: str) -> list[dict]:
+    conn = UnixHTTPConnection(socket_path)
+    try:
+        conn.request("GET", "/containers/json")
+        resp = conn.getresponse()
+        body = resp.read()
+    finally:
+        conn.close()
+    if resp.status != 200:
+        raise RuntimeError(f"Docker API returned {resp.status}: {body.decode(errors='replace')}")
+    return json.loads(body)
+
+
+def discover_targets(socket_path: str) -> list[dict]:
+    """Derive targets from the labels of all running containers
+
+    A container labeled with `hetzner-ddns.hostname=app.example.de` results in the target `app` in zone `example.de`.
+    Since the zone can not be derived reliably for every hostname, it can be given explicitly with the
+    `hetzner-ddns.zone` label.
+    """
+    targets = []
+    for container in list_containers(socket_path):
+        labels = container.get("Labels") or {}
+        for hostname in labels.get(LABEL_HOSTNAME, "").split(","):
+            hostname = hostname.strip().rstrip(".")
+            if not hostname:
+                continue
+            zone = labels.get(LABEL_ZONE) or ".".join(hostname.split(".")[-2:])
+            record = hostname.removesuffix(zone).rstrip(".") or "@"
+            target = {"zone": zone, "record": record}
+            if target not in targets:
+                targets.append(target)
+    return targets
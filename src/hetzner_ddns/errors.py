@@ -0,0 +1,45 @@
+import json
+import sys
+
+import requests
+from hetzner_dns_api import InvalidResponseError
+
+from hetzner_ddns.state import LockedError
+
+
+def describe(error: BaseException) -> dict:
+    """Machine-readable description of an error which made hetzner_ddns fail
+
+    `code` identifies the kind of error, `category` groups them and `retryable` tells whether running again later
+    might succeed without changing anything.
+    """
+    target = getattr(error, "target", None)
+    cause = error.__cause__ if target is not None and error.__cause__ is not None else error
+    match cause:
+        case requests.HTTPError() if cause.response is not None:
+            status = cause.response.status_code
+            code, category, retryable = f"http_{status}", "http", status == 429 or status >= 500
+        case InvalidResponseError():
+            code, category, retryable = "invalid_response", "http", False
+        case requests.Timeout():
+            code, category, retryable = "timeout", "network", True
+        case requests.ConnectionError():
+            code, category, retryable = "connection", "network", True
+        case LockedError():
+            code, category, retryable = "locked", "state", True
+        case OSError():
+            code, category, retryable = "io", "system", False
+        case _:
+            code, category, retryable = "internal", "internal", False
+    return {
+        "code": code,
+        "category": category,
+        "target": f"{target['record']}.{target['zone']}" if target is not None else None,
+        "retryable": retryable,
+        "type": type(cause).__name__,
+        "message": str(cause),
+    }
+
+
+def print_json(error: BaseException):
+    print(json.dumps({"error": describe(error)}), file=sys.stderr, flush=True)
@@ -0,0 +1,52 @@
+import errno
+import json
+import logging
+import os
+import sys
+from datetime import datetime, timezone
+
+logger = logging.getLogger(__name__)
+
+# path of the file or fifo events are written to, "-" for stdout or None if disabled
+target: str | None = None
+fd: int | None = None
+
+
+def setup(path: str | None):
+    global target
+    target = path
+
+
+def open_target() -> int | None:
+    global fd
+    if fd is None:
+        if target == "-":
+            fd = sys.stdout.fileno()
+        else:
+            try:
+                # a fifo without reader must not block the daemon
+                fd = os.open(target, os.O_WRONLY | os.O_APPEND | os.O_CREAT | os.O_NONBLOCK)
+            except OSError as e:
+                if e.errno != errno.ENXIO:
+                    logger.warning("Could not open %s: %s", target, e)
+                return None
+    return fd
+
+
+def emit(event: str, **fields):
+    """Write an event as json line to the configured stream, dropping it if nobody is reading"""
+    if target is None or open_target() is None:
+        return
+    line = json.dumps({"event": event, "time": datetime.now(timezone.utc).isoformat(timespec="seconds"), **fields})
+    global fd
+    try:
+        os.write(fd, (line + "\n").encode())
+    except BlockingIOError:
+        pass
+    except OSError as e:
+        if e.errno != errno.EPIPE:
+            logger.warning("Could not write event to %s: %s", target, e)
+        # the reader of a fifo went away
+        if target != "-":
+            os.close(fd)
+        fd = None
@@ -0,0 +1,37 @@
+import plistlib
+import shutil
+import sys
+from pathlib import Path
+
+LABEL = "hetzner_ddns"
+
+
+def program_arguments(config: Path, profile: str | None) -> list[str]:
+    """Command line running this installation with the given config"""
+    if sys.argv[0].endswith(".py"):
+        # started with `python -m hetzner_ddns.main`
+        args = [sys.executable, "-m", "hetzner_ddns.main"]
+    else:
+        args = [str(Path(shutil.which(sys.argv[0]) or sys.argv[0]).resolve())]
+    args += ["-c", str(config.resolve())]
+    if profile is not None:
+        args += ["-p", profile]
+    return args
+
+
+def launchd_plist(config: Path, profile: str | None, interval: float, system: bool) -> bytes:
+    """A launchd job which runs an update every `interval` seconds
+
+    With `system`, the job is meant to be installed as LaunchDaemon in `/Library/LaunchDaemons`, otherwise as
+    LaunchAgent in `~/Library/LaunchAgents`.
+    """
+    log_file = "/var/log/hetzner_ddns.log" if system else str(Path.home() / "Library" / "Logs" / "hetzner_ddns.log")
+    job = {
+        "Label": LABEL,
+        "ProgramArguments": [*program_arguments(config, profile), "update"],
+        "StartInterval": round(interval),
+        "RunAtLoad": True,
+        "StandardOutPath": log_file,
+        "StandardErrorPath": log_file,
+    }
+    return plistlib.dumps(job)
@@ -0,0 +1,79 @@
+import json
+import socket
+import threading
+import time
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+# outcome of the latest update of the daemon and when it intends to start the next one
+last_run: float | None = None
+last_run_success = False
+next_run: float | None = None
+
+
+def record_run(success: bool):
+    global last_run, last_run_success
+    last_run = time.time()
+    last_run_success = success
+
+
+def schedule_run(delay: float):
+    global next_run
+    next_run = time.time() + delay
+
+
+class HealthHandler(BaseHTTPRequestHandler):
+    """Serves the health endpoints for container healthchecks and kubernetes probes
+
+    `/healthz` fails if an update is overdue by more than `max_age`, e.g. because the previous one hangs, and `/readyz`
+    fails if the latest update failed.
+    """
+
+    server: "HealthServer"
+
+    def do_GET(self):
+        age = round(time.time() - last_run, 1) if last_run is not None else None
+        match self.path:
+            case "/healthz":
+                # waiting for the next update, even when backing off after failures, is healthy
+                due = next_run if next_run is not None else self.server.started
+                ok = time.time() - due < self.server.max_age
+            case "/readyz":
+                ok = last_run_success
+            case _:
+                self.send_error(404)
+                return
+        body = json.dumps({"ok": ok, "last_run_success": last_run_success, "seconds_since_last_run": age}).encode()
+        self.send_response(200 if ok else 503)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(body)))
+        self.end_headers()
+        self.wfile.write(body)
+
+    def log_message(self, format, *args):
+        # probes would flood the log
+        pass
+
+
+class HealthServer(ThreadingHTTPServer):
+    daemon_threads = True
+
+    def __init__(self, address: tuple[str, int], max_age: float):
+        self.max_age = max_age
+        self.started = time.time()
+        super().__init__(address, HealthHandler)
+
+
+class HealthServer6(HealthServer):
+    address_family = socket.AF_INET6
+
+
+def bind(listen: str, max_age: float) -> HealthServer:
+    """Bind the health endpoint to `<host>:<port>`, which may need privileges and therefore happens early"""
+    host, _, port = listen.rpartition(":")
+    host = host.strip("[]") or "0.0.0.0"
+    server_class = HealthServer6 if ":" in host else HealthServer
+    return server_class((host, int(port)), max_age)
+
+
+def serve(server: HealthServer):
+    threading.Thread(target=server.serve_forever, name="health", daemon=True).start()
@@ -0,0 +1,24 @@
+import logging
+
+import requests
+
+from hetzner_ddns.logs import run_id
+
+logger = logging.getLogger(__name__)
+
+
+def ping(cfg: dict, event: str = "", message: str = ""):
+    """Ping a healthchecks.io compatible check
+
+    `event` is appended to the check url, i.e. `"start"` or `"fail"`, or empty for signaling success.
+    """
+    if "heartbeat_url" not in cfg:
+        return
+    url = cfg["heartbeat_url"].rstrip("/")
+    if event:
+        url = f"{url}/{event}"
+    try:
+        resp = requests.post(url, params={"rid": run_id.get()}, data=message.encode()[:10000], timeout=10)
+        resp.raise_for_status()
+    except requests.RequestException as e:
+        logger.warning("Could not ping heartbeat url: %s", e)
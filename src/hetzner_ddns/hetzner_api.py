@@ -1,36 +1,102 @@
+import time
+from collections.abc import Callable
+from itertools import count
+
 import requests
-from requests.auth import AuthBase
-from requests import PreparedRequest
+from hetzner_dns_api import DEFAULT_TIMEOUT, Client, Send, retry
+from urllib3.util import Timeout
+
+from hetzner_ddns import __version__, metrics, pool
+from hetzner_ddns.logs import log_http_response, run_id
+from hetzner_ddns.resolve import ResolvingAdapter
 
 
-HETZNER_URL = "https://dns.hetzner.com/api/v1"
+class HetznerApi(Client):
+    """The api client with request ids, logging, metrics and pacing of updates"""
 
+    def __init__(
+        self,
+        api_token: str,
+        update_rate: float | None = None,
+        url: str | None = None,
+        retries: int = 0,
+        proxy: str | None = None,
+        resolve: Callable[[str], str] | None = None,
+        timeout: Timeout = DEFAULT_TIMEOUT,
+    ):
+        """Create a client which retries failed requests up to `retries` times
 
-class HetznerAuth(AuthBase):
-    def __init__(self, api_token: str):
-        self.api_token = api_token
+        `update_rate` limits record updates to that many per second to stay within the api's rate limits.
+        `resolve` replaces the system resolver for looking up the address of the api.
+        """
+        self.request_counter = count(1)
+        middleware = [retry(attempts=retries + 1), self.trace] if retries else [self.trace]
+        super().__init__(
+            api_token,
+            url=url,
+            timeout=timeout,
+            user_agent=f"hetzner_ddns/{__version__}",
+            proxies={"http": proxy, "https": proxy} if proxy else None,
+            middleware=middleware,
+        )
+        self.sess.hooks["response"].append(log_http_response)
+        pool.mount(self.sess, {self.url: ResolvingAdapter(resolve)} if resolve is not None else None)
+        self.update_interval = 1 / update_rate if update_rate else 0
+        self.last_update = 0.0
+        # record listings by zone id, so that targets in the same zone share one request
+        self.records_cache: dict[str, list[dict]] = {}
 
-    def __call__(self, r: PreparedRequest) -> PreparedRequest:
-        r.headers["Auth-API-Token"] = self.api_token
-        return r
+    def trace(self, send: Send) -> Send:
+        """Middleware which tags each request with an id and records its duration"""
 
+        def traced_send(method: str, path: str, **kwargs) -> requests.Response:
+            request_id = f"{run_id.get()}-{next(self.request_counter)}"
+            kwargs["headers"] = {"X-Request-Id": request_id, **kwargs.get("headers", {})}
+            endpoint = path.split("?")[0].split("/")[1]
+            start = time.monotonic()
+            try:
+                resp = send(method, path, **kwargs)
+            except requests.RequestException:
+                metrics.observe(
+                    "hetzner_ddns_api_request_duration_seconds",
+                    time.monotonic() - start,
+                    method=method,
+                    endpoint=endpoint,
+                    status="error",
+                )
+                raise
+            metrics.observe(
+                "hetzner_ddns_api_request_duration_seconds",
+                time.monotonic() - start,
+                method=method,
+                endpoint=endpoint,
+                status=resp.status_code,
+            )
+            return resp
 
-class HetznerApi:
-    def __init__(self, api_token: str):
-        self.sess = requests.Session()
-        self.sess.auth = HetznerAuth(api_token)
+        return traced_send
 
-    def get_zone(self, zone_name: str):
-        resp = self.sess.get(f"{HETZNER_URL}/zones?name={zone_name}")
-        resp.raise_for_status()
-        return resp.json()["zones"][0]
+    def request(self, method: str, path: str, **kwargs) -> dict:
+        try:
+            return super().request(method, path, **kwargs)
+        except requests.HTTPError as e:
+            request_id = e.response.request.headers["X-Request-Id"]
+            msg = f"{e} (request id {request_id}"
+            api_request_id = e.response.headers.get("X-Request-Id")
+            if api_request_id and api_request_id != request_id:
+                msg += f", api request id {api_request_id}"
+            raise requests.HTTPError(msg + ")", response=e.response) from None
 
-    def get_records(self, zone_id: str):
-        resp = self.sess.get(f"{HETZNER_URL}/records?zone_id={zone_id}")
-        resp.raise_for_status()
-        return resp.json()["records"]
+    def get_records(self, zone_id: str, per_page: int | None = None) -> list[dict]:
+        if zone_id not in self.records_cache:
+            self.records_cache[zone_id] = super().get_records(zone_id, per_page)
+        return self.records_cache[zone_id]
 
-    def update_record(self, record_id: str, data: dict):
-        resp = self.sess.put(f"{HETZNER_URL}/records/{record_id}", json=data)
-        resp.raise_for_status()
-        return resp.json()
+    def update_record(self, record_id: str, data: dict) -> dict:
+        time.sleep(max(0.0, self.last_update + self.update_interval - time.monotonic()))
+        self.last_update = time.monotonic()
+        result = super().update_record(record_id, data)
+        for i in self.records_cache.get(data["zone_id"], []):
+            if i["id"] == record_id:
+                i.update(data)
+        return result
@@ -0,0 +1,20 @@
+import logging
+import os
+import subprocess
+
+logger = logging.getLogger(__name__)
+
+
+def run_hooks(commands: list[str], env: dict[str, str]):
+    """Run shell commands with the given additional environment variables, logging (but otherwise ignoring) failures"""
+    for command in commands:
+        logger.info("Running hook %s", command)
+        try:
+            result = subprocess.run(
+                command, shell=True, env={**os.environ, **env}, capture_output=True, text=True, timeout=300
+            )
+        except subprocess.TimeoutExpired:
+            logger.warning("Hook %s timed out", command)
+            continue
+        if result.returncode != 0:
+            logger.warning("Hook %s failed with exit code %s: %s", command, result.returncode, result.stderr.strip())
@@ -1,15 +1,55 @@
 from functools import cache
 import requests
+from urllib3.util import Timeout
+
+from hetzner_ddns import pool
+from hetzner_ddns.logs import log_http_response, register_secret
+from hetzner_ddns.pinning import PinningAdapter
+
+session = requests.Session()
+proxies: dict[str, str] = {}
+# a dead echo service must not delay every run by the much longer timeouts of the api
+timeout = Timeout(total=5)
+
+
+pinning_adapter: PinningAdapter | None = None
+# url and optionally `headers`, `username` and `password` of the services detecting each address family
+sources = {"ipv4": {"url": "https://4.kritzl.dev"}, "ipv6": {"url": "https://6.kritzl.dev"}}
+
+
+def setup(
+    proxy: str | None, total_timeout: float, pins: list[str] | None = None, custom_sources: dict | None = None
+):
+    global proxies, timeout, pinning_adapter
+    proxies = {"http": proxy, "https": proxy} if proxy else {}
+    timeout = Timeout(total=total_timeout)
+    pinning_adapter = PinningAdapter(pins) if pins else None
+    for family, source in (custom_sources or {}).items():
+        sources[family] = source
+        register_secret(source.get("password"))
+        for value in source.get("headers", {}).values():
+            register_secret(value)
+
+
+def get(source: dict) -> str:
+    pool.mount(session, {"https://": pinning_adapter} if pinning_adapter is not None else None)
+    resp = session.get(
+        source["url"],
+        headers=source.get("headers"),
+        auth=(source["username"], source["password"]) if "username" in source else None,
+        hooks={"response": log_http_response},
+        proxies=proxies,
+        timeout=timeout,
+    )
+    resp.raise_for_status()
+    return resp.text.strip()
+
 
 @cache
 def get_ipv4() -> str:
-    resp = requests.get("https://4.kritzl.dev")
-    resp.raise_for_status()
-    return resp.text
+    return get(sources["ipv4"])
 
 
 @cache
-def get_ipv6():
-    resp = requests.get("https://6.kritzl.dev")
-    resp.raise_for_status()
-    return resp.text
+def get_ipv6() -> str:
+    return get(sources["ipv6"])
@@ -0,0 +1,232 @@
+import json
+import logging
+import logging.handlers
+import os
+import re
+import socket
+import struct
+import sys
+import uuid
+from contextlib import contextmanager
+from contextvars import ContextVar
+from datetime import datetime, timezone
+
+import requests
+
+# extra fields which are attached to log records and emitted under stable names by structured formatters
+LOG_FIELDS = ("run_id", "request_id", "record", "zone", "type", "old", "new", "ipv4", "ipv6", "result", "duration")
+
+HTTP_BODY_LIMIT = 1000
+SECRET_HEADERS = {"auth-api-token", "authorization", "proxy-authorization"}
+# long random looking strings mixing upper-, lowercase characters and digits (but not lowercase hex ids)
+TOKEN_PATTERN = re.compile(
+    r"\b(?=[A-Za-z0-9_-]*[A-Z])(?=[A-Za-z0-9_-]*[a-z])(?=[A-Za-z0-9_-]*[0-9])[A-Za-z0-9_-]{24,}\b"
+)
+
+http_logger = logging.getLogger("hetzner_ddns.http")
+secrets = set()
+run_id: ContextVar[str] = ContextVar("run_id", default="none")
+# fields which are attached to all log records emitted in the current context
+context_fields: ContextVar[dict] = ContextVar("context_fields", default={})
+
+JOURNALD_SOCKET = "/run/systemd/journal/socket"
+JOURNALD_PRIORITIES = {
+    logging.CRITICAL: 2,
+    logging.ERROR: 3,
+    logging.WARNING: 4,
+    logging.INFO: 6,
+    logging.DEBUG: 7,
+}
+# results of record updates -> windows event id, other events are identified by their level (20 for info etc.)
+EVENTLOG_IDS = {"updated": 100, "restored": 101, "skipped": 102, "unchanged": 103}
+
+
+def new_run_id() -> str:
+    """Generate a new id which correlates all log lines and api calls of one run"""
+    value = str(uuid.uuid4())
+    run_id.set(value)
+    return value
+
+
+@contextmanager
+def log_context(**fields):
+    """Attach the given fields to all log records emitted within the context"""
+    token = context_fields.set({**context_fields.get(), **fields})
+    try:
+        yield
+    finally:
+        context_fields.reset(token)
+
+
+def update_log_context(**fields):
+    context_fields.set({**context_fields.get(), **fields})
+
+
+class ContextFilter(logging.Filter):
+    def filter(self, record: logging.LogRecord) -> bool:
+        if secrets and isinstance(record.msg, str):
+            # e.g. urllib3 logs the urls of requests, which may contain tokens
+            message = record.getMessage()
+            if any(i in message for i in secrets):
+                record.msg, record.args = redact_secrets(message), ()
+        record.run_id = run_id.get()
+        for k, v in context_fields.get().items():
+            if not hasattr(record, k):
+                setattr(record, k, v)
+        return True
+
+
+class JsonFormatter(logging.Formatter):
+    def format(self, record: logging.LogRecord) -> str:
+        data = {
+            "time": datetime.fromtimestamp(record.created, timezone.utc).isoformat(),
+            "level": record.levelname.lower(),
+            "logger": record.name,
+            "message": record.getMessage(),
+        }
+        for field in LOG_FIELDS:
+            if hasattr(record, field):
+                data[field] = getattr(record, field)
+        if record.exc_info:
+            data["error"] = self.formatException(record.exc_info)
+        return json.dumps(data)
+
+
+class JournaldHandler(logging.Handler):
+    """Log handler speaking the native journald protocol so that extra fields become journal metadata"""
+
+    def __init__(self, socket_path: str = JOURNALD_SOCKET):
+        super().__init__()
+        self.socket_path = socket_path
+        self.sock = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM)
+
+    def emit(self, record: logging.LogRecord):
+        try:
+            fields = {
+                "MESSAGE": self.format(record),
+                "PRIORITY": JOURNALD_PRIORITIES.get(record.levelno, 6),
+                "SYSLOG_IDENTIFIER": "hetzner_ddns",
+                "LOGGER": record.name,
+                "CODE_FILE": record.pathname,
+                "CODE_LINE": record.lineno,
+                "CODE_FUNC": record.funcName,
+            }
+            for field in LOG_FIELDS:
+                if hasattr(record, field):
+                    fields[field.upper()] = getattr(record, field)
+            self.sock.sendto(b"".join(self.encode_field(k, v) for k, v in fields.items()), self.socket_path)
+        except Exception:
+            self.handleError(record)
+
+    @staticmethod
+    def encode_field(key: str, value) -> bytes:
+        value = str(value).encode()
+        if b"\n" in value:
+            # multi-line values need to be length-prefixed
+            return key.encode() + b"\n" + struct.pack("<Q", len(value)) + value + b"\n"
+        return key.encode() + b"=" + value + b"\n"
+
+    def close(self):
+        self.sock.close()
+        super().close()
+
+
+class EventLogHandler(logging.handlers.NTEventLogHandler):
+    """Writes record updates and warnings or errors to the windows event log (requires the `windows` extra)"""
+
+    def __init__(self):
+        super().__init__("hetzner_ddns")
+        self.addFilter(lambda record: hasattr(record, "result") or record.levelno >= logging.WARNING)
+
+    def getEventID(self, record: logging.LogRecord) -> int:
+        return EVENTLOG_IDS.get(getattr(record, "result", None), record.levelno)
+
+
+def is_journald_stream() -> bool:
+    """Whether stderr is connected to the journal, i.e. the process is running as a systemd service"""
+    journal_stream = os.environ.get("JOURNAL_STREAM")
+    if not journal_stream or not os.path.exists(JOURNALD_SOCKET):
+        return False
+    try:
+        stat = os.fstat(sys.stderr.fileno())
+    except (OSError, ValueError):
+        return False
+    return journal_stream == f"{stat.st_dev}:{stat.st_ino}"
+
+
+def register_secret(secret: str):
+    """Make sure the given value never appears in logs"""
+    if secret:
+        secrets.add(secret)
+
+
+def redact_secrets(text: str) -> str:
+    for secret in secrets:
+        text = text.replace(secret, "<redacted>")
+    return text
+
+
+def redact(text: str) -> str:
+    return TOKEN_PATTERN.sub("<redacted>", redact_secrets(text))
+
+
+def format_http_body(body) -> str:
+    if body is None:
+        return ""
+    if isinstance(body, bytes):
+        body = body.decode(errors="replace")
+    if len(body) > HTTP_BODY_LIMIT:
+        body = body[:HTTP_BODY_LIMIT] + f"... ({len(body)} characters)"
+    return redact(body)
+
+
+def log_http_response(resp: requests.Response, *args, **kwargs):
+    """Response hook for requests which traces the request and response at debug level"""
+    if not http_logger.isEnabledFor(logging.DEBUG):
+        return
+    req = resp.request
+    headers = {k: "<redacted>" if k.lower() in SECRET_HEADERS else redact(v) for k, v in req.headers.items()}
+    http_logger.debug(
+        "%s %s -> %s %s in %.3fs\nrequest headers: %s\nrequest body: %s\nresponse body: %s",
+        req.method,
+        redact(req.url),
+        resp.status_code,
+        resp.reason,
+        resp.elapsed.total_seconds(),
+        headers,
+        format_http_body(req.body),
+        format_http_body(resp.text),
+        extra={"request_id": req.headers.get("X-Request-Id")},
+    )
+
+
+def file_handler(cfg: dict) -> logging.Handler:
+    if "log_rotate_when" in cfg:
+        return logging.handlers.TimedRotatingFileHandler(
+            cfg["log_file"], when=cfg["log_rotate_when"], backupCount=cfg.get("log_backup_count", 5)
+        )
+    return logging.handlers.RotatingFileHandler(
+        cfg["log_file"], maxBytes=cfg.get("log_max_bytes", 10 * 1024 * 1024), backupCount=cfg.get("log_backup_count", 5)
+    )
+
+
+def setup_logging(log_format: str, cfg: dict, verbosity: int = 0):
+    if log_format == "auto":
+        log_format = "journald" if "log_file" not in cfg and is_journald_stream() else "text"
+
+    if log_format == "journald":
+        handler = JournaldHandler()
+    elif log_format == "eventlog":
+        handler = EventLogHandler()
+        handler.setFormatter(logging.Formatter("%(message)s (run %(run_id)s)"))
+    else:
+        handler = file_handler(cfg) if "log_file" in cfg else logging.StreamHandler(sys.stderr)
+        if log_format == "json":
+            handler.setFormatter(JsonFormatter())
+        else:
+            handler.setFormatter(logging.Formatter("%(asctime)s %(levelname)s [%(run_id)s] %(message)s"))
+    handler.addFilter(ContextFilter())
+    logging.basicConfig(level=logging.DEBUG if verbosity > 0 else logging.INFO, handlers=[handler])
+    # tracing of every http request is only shown at -vv
+    http_logger.setLevel(logging.DEBUG if verbosity > 1 else logging.INFO)
+    register_secret(cfg.get("api_token"))
@@ -1,52 +1,662 @@
 #!/usr/bin/env python3
 import argparse
-import tomllib
+import json
+import logging
+import random
+import signal
+import subprocess
+import sys
+import threading
+import time
+import traceback
+from datetime import datetime, timedelta, timezone
+from functools import partial
 from pathlib import Path
 
-from hetzner_ddns.hetzner_api import HetznerApi
-from hetzner_ddns import kritzl_dev
+from hetzner_dns_api import RecordType
+
+from hetzner_ddns.backup import commit_snapshot, get_managed_records, write_backup
+from hetzner_ddns.cassette import Cassette
+from hetzner_ddns.config import load_config, parse_duration
+from hetzner_ddns.logs import log_context, new_run_id, setup_logging, update_log_context
+from hetzner_ddns.providers import Provider, Providers
+from hetzner_ddns.schedule import CronSchedule
+from hetzner_ddns.state import LockedError, StateStore, default_state_dir
+from hetzner_ddns import (
+    docker,
+    errors,
+    events,
+    generate,
+    health,
+    heartbeat,
+    hooks,
+    kritzl_dev,
+    metrics,
+    mqtt,
+    netif,
+    notify,
+    output,
+    pool,
+    privileges,
+    sandbox,
+    systemd,
+    telemetry,
+    windows,
+)
+
+logger = logging.getLogger(__name__)
+
+
+class TargetError(Exception):
+    """Processing of a single target failed"""
+
+    def __init__(self, target: dict, remaining: list[dict]):
+        super().__init__(f"Could not update target {target['record']}.{target['zone']}")
+        self.target = target
+        # the failed target and all which were not processed because of it
+        self.remaining = remaining
 
 
 def main():
     argp = argparse.ArgumentParser(prog="hetzner_ddns", description="DynDNS client for Hetzner DNS")
     argp.add_argument("-c", "--config", type=Path, required=True, help="Path to a config.toml")
+    argp.add_argument("-p", "--profile", help="Name of the config profile to use")
+    argp.add_argument(
+        "-v", "--verbose", action="count", default=0, help="Increase log verbosity (-vv traces all http requests)"
+    )
+    argp.add_argument(
+        "--log-format",
+        choices=["auto", "text", "json", "journald", "eventlog"],
+        default="auto",
+        help="Format of log output (default: journald when running as a systemd service, text otherwise)",
+    )
+    argp.add_argument(
+        "--events",
+        choices=["ndjson"],
+        help="Write a json line for every detected address, updated record and failure to --events-file",
+    )
+    argp.add_argument(
+        "--events-file", default="-", help="File or fifo events are written to (default: - for stdout)"
+    )
+    argp.add_argument(
+        "--error-format",
+        choices=["text", "json"],
+        default="text",
+        help="Additionally print a json object describing the error to stderr on failure with json",
+    )
+    cassette_g = argp.add_mutually_exclusive_group()
+    cassette_g.add_argument(
+        "--record-http", type=Path, metavar="CASSETTE", help="Record all http requests and responses to a json file"
+    )
+    cassette_g.add_argument(
+        "--replay-http", type=Path, metavar="CASSETTE", help="Answer http requests from a recorded json file"
+    )
+    subp = argp.add_subparsers(dest="command")
+    subp.add_parser("update", help="Update all configured targets (default)")
+    subp.add_parser("daemon", help="Keep running and update all configured targets periodically")
+    subp.add_parser("windows-service", help="Run the daemon as windows service (only used by the service manager)")
+    history_p = subp.add_parser("history", help="Show past IP changes and the records that were updated")
+    history_p.add_argument("record", nargs="?", help="Only show updates of this record")
+    history_p.add_argument("--json", action="store_true", help="Print the history as json")
+    rollback_p = subp.add_parser("rollback", help="Restore the values records had before they were updated")
+    rollback_g = rollback_p.add_mutually_exclusive_group()
+    rollback_g.add_argument("--run", type=int, help="Undo all updates of this run (default: the latest run)")
+    rollback_g.add_argument("--record", help="Undo the latest update of this record")
+    backup_p = subp.add_parser("backup", help="Dump all records managed by this config into a file")
+    backup_p.add_argument("file", type=Path, help="Path of the backup file (.json or .toml)")
+    generate_p = subp.add_parser("generate", help="Print configuration which schedules updates with this config")
+    generate_p.add_argument("kind", choices=["launchd"], help="What to generate")
+    generate_p.add_argument(
+        "--system", action="store_true", help="Generate a system wide LaunchDaemon instead of a LaunchAgent"
+    )
     args = argp.parse_args()
 
-    cfg = load_config(args.config)
-    api = HetznerApi(cfg["api_token"])
-    for target in cfg["targets"]:
-        process_target(api, target)
+    cfg = load_config(args.config, args.profile)
+    setup_logging(args.log_format, cfg, args.verbose)
+    health_server = None
+    if args.command in ("daemon", "windows-service") and "health_listen" in cfg:
+        health_server = health.bind(cfg["health_listen"], parse_duration(cfg.get("health_max_age", "15m")))
+    # everything that needs root privileges (like opening the log file) has to happen before this
+    privileges.drop_privileges(cfg)
+    store = StateStore(Path(cfg.get("state_dir", default_state_dir())))
+    # before any threads are started, which would not be restricted
+    sandbox.apply_sandbox(cfg, args.config, store.path)
+    if health_server is not None:
+        health.serve(health_server)
+    new_run_id()
+    events.setup(args.events_file if args.events else None)
+    telemetry.setup(cfg)
+    telemetry.setup_sentry(cfg)
+    metrics.load(store)
+    pool.setup_tls(cfg.get("tls_backend", "certifi"))
+    http_cassette = None
+    if args.record_http or args.replay_http:
+        http_cassette = Cassette(args.record_http or args.replay_http, replay=args.replay_http is not None)
+    pool.setup(
+        cfg.get("http_max_idle_per_host", 10),
+        parse_duration(cfg.get("http_pool_idle_timeout", "90s")),
+        http_cassette,
+    )
+    kritzl_dev.setup(
+        cfg.get("detection_proxy"),
+        parse_duration(cfg.get("detection_timeout", "5s")),
+        cfg.get("detection_pins"),
+        cfg.get("detection_sources"),
+    )
+    match args.command:
+        case "history":
+            print_history(store, args.record, args.json)
+        case "backup":
+            records = get_managed_records(Providers(cfg, store), get_targets(cfg))
+            write_backup(records, args.file)
+            print(f"Wrote {len(records)} records to {args.file}")
+        case "generate":
+            plist = generate.launchd_plist(
+                args.config, args.profile, parse_duration(cfg.get("interval", "5m")), args.system
+            )
+            sys.stdout.buffer.write(plist)
+        case _:
+            try:
+                run_locked_command(args, cfg, store)
+            except LockedError as e:
+                if args.error_format != "json":
+                    sys.exit(f"Another instance is already running: {e}")
+                print(f"Another instance is already running: {e}", file=sys.stderr)
+                errors.print_json(e)
+                sys.exit(1)
+            except Exception as e:
+                if args.error_format != "json":
+                    raise
+                traceback.print_exc()
+                errors.print_json(e)
+                sys.exit(1)
 
 
-def load_config(path: Path) -> dict:
-    with open(path, "rb") as f:
-        return tomllib.load(f)
+def run_locked_command(args: argparse.Namespace, cfg: dict, store: StateStore):
+    # commands which modify records must not run concurrently
+    with store.lock(parse_duration(cfg.get("lock_timeout", 0))):
+        match args.command:
+            case "rollback":
+                rollback(cfg, store, args.run, args.record)
+            case "daemon":
+                run_daemon(cfg, store)
+            case "windows-service":
+                windows.run_service(lambda wakeup, stop: run_daemon(cfg, store, wakeup, stop))
+            case _:
+                time.sleep(startup_delay(cfg))
+                run_update(cfg, store)
 
 
-def process_target(api: HetznerApi, target: dict):
-    print(f"Processing target {target['record']}.{target['zone']}")
-    zone = api.get_zone(target["zone"])
-    records = (i for i in api.get_records(zone["id"]) if i["name"] == target["record"])
-    for i_record in records:
-        match i_record["type"]:
-            case "A":
-                new_value = kritzl_dev.get_ipv4()
-            case "AAAA":
-                new_value = kritzl_dev.get_ipv6()
-            case _:
+def get_targets(cfg: dict) -> list[dict]:
+    targets = list(cfg.get("targets", []))
+    if cfg.get("docker_discovery", False):
+        for target in docker.discover_targets(cfg.get("docker_socket", "/var/run/docker.sock")):
+            if target not in targets:
+                targets.append(target)
+    return targets
+
+
+def run_daemon(
+    cfg: dict, store: StateStore, wakeup: threading.Event | None = None, stop: threading.Event | None = None
+):
+    """Update all targets every `interval` or according to `schedule` until terminated or `stop` is set
+
+    Additionally, an update is started immediately whenever SIGUSR1 is received or `wakeup` is set.
+    """
+    interval = parse_duration(cfg.get("interval", "5m"))
+    schedule = CronSchedule(cfg["schedule"]) if "schedule" in cfg else None
+    jitter = parse_duration(cfg.get("jitter", 0))
+    backoff_max = parse_duration(cfg.get("backoff_max", "1h"))
+    max_failures = cfg.get("max_consecutive_failures", 0)
+    target_retry_interval = parse_duration(cfg.get("target_retry_interval", "30s"))
+    max_target_retries = cfg.get("target_retries", 3)
+    failures = 0
+    target_retries = 0
+    retry_targets = None
+    watchdog = systemd.watchdog_interval()
+    wakeup = wakeup or threading.Event()
+    stop = stop or threading.Event()
+    # not available on windows
+    if hasattr(signal, "SIGUSR1"):
+
+        def on_sigusr1(*_):
+            logger.info("Received SIGUSR1, updating immediately")
+            wakeup.set()
+
+        signal.signal(signal.SIGUSR1, on_sigusr1)
+    systemd.sd_notify(READY=1)
+    wait(startup_delay(cfg), wakeup, watchdog)
+    while not stop.is_set():
+        # detected addresses are only cached for the duration of one run
+        kritzl_dev.get_ipv4.cache_clear()
+        kritzl_dev.get_ipv6.cache_clear()
+        now = datetime.now().isoformat(sep=" ", timespec="seconds")
+        try:
+            run_update(cfg, store, retry_targets)
+        except Exception as e:
+            health.record_run(False)
+            logger.exception("Update failed")
+            systemd.sd_notify(STATUS=f"Last update at {now} failed: {e}")
+            if isinstance(e, TargetError) and target_retries < max_target_retries:
+                # retry just the failed target and the ones after it soon instead of waiting for the next update
+                target_retries += 1
+                retry_targets = e.remaining
+                logger.info("Retrying %s target(s) in %ss", len(retry_targets), round(target_retry_interval))
+                wait(target_retry_interval, wakeup, watchdog)
+                new_run_id()
                 continue
+            failures += 1
+            if failures == max_failures:
+                # leave recovery to the service manager
+                sys.exit(f"Giving up after {failures} consecutive failed updates")
+        else:
+            health.record_run(True)
+            failures = 0
+            # unchanged targets are skipped, so the addresses of this run alone are usually empty
+            addresses = ", ".join(sorted(set(store.get_published_addresses().values()))) or "no records"
+            systemd.sd_notify(STATUS=f"Last update at {now}: {addresses}")
+        target_retries = 0
+        retry_targets = None
+
+        if schedule is not None:
+            now = datetime.now()
+            delay = (schedule.next_after(now) - now).total_seconds()
+        else:
+            delay = interval
+        if failures:
+            # don't keep hammering the apis while something is broken
+            backoff = min(interval * 2**failures, backoff_max)
+            logger.info("Backing off for %ss after %s consecutive failures", round(backoff), failures)
+            delay = max(delay, backoff)
+        # desynchronizes instances which were started at the same time
+        wait(delay + random.uniform(0, jitter), wakeup, watchdog)
+        new_run_id()
+
+
+def wait(seconds: float, wakeup: threading.Event, watchdog: float | None):
+    """Sleep while keeping the systemd watchdog happy, unless `wakeup` is set in the meantime"""
+    health.schedule_run(seconds)
+    deadline = time.monotonic() + seconds
+    while not wakeup.is_set() and (remaining := deadline - time.monotonic()) > 0:
+        systemd.sd_notify(WATCHDOG=1)
+        wakeup.wait(min(remaining, watchdog) if watchdog else remaining)
+    wakeup.clear()
+
+
+def startup_delay(cfg: dict) -> float:
+    """A random delay of up to `startup_delay_max` so that many hosts starting at once don't update simultaneously"""
+    delay = random.uniform(0, parse_duration(cfg.get("startup_delay_max", 0)))
+    if delay:
+        logger.info("Waiting %.1fs before the first update", delay)
+    return delay
+
+
+def run_update(cfg: dict, store: StateStore, targets: list[dict] | None = None) -> list[dict]:
+    """Update the given targets (by default all configured ones)"""
+    start = time.time()
+    metrics.gauge("hetzner_ddns_last_run_timestamp_seconds", start)
+    heartbeat.ping(cfg, "start")
+    notifiers = notify.create_notifiers(cfg)
+    throttle = notify.Throttle(store, parse_duration(cfg.get("notification_throttle", "1h")))
+    try:
+        with telemetry.span("update"):
+            updates = update_targets(cfg, store, notifiers, throttle, targets)
+    except Exception as e:
+        if isinstance(e, TargetError):
+            target = e.target
+            error = e.__cause__
+        else:
+            target = {"record": None, "zone": None}
+            error = e
+        record = f"{target['record']}.{target['zone']}" if target["zone"] else None
+        telemetry.report_error(error, target=record, zone=target["zone"])
+        if throttle.should_notify_failure(record or "run"):
+            notify.notify(
+                notifiers, notify.make_event("update_failed", record=record, zone=target["zone"], error=str(error))
+            )
+        hooks.run_hooks(
+            cfg.get("on_failure", []),
+            {
+                # the exit code hetzner_ddns itself is going to exit with
+                "EXIT_CODE": "1",
+                "ERROR": str(error),
+                "ERROR_TYPE": type(error).__name__,
+                "RECORD": record or "",
+                "ZONE": target["zone"] or "",
+            },
+        )
+        events.emit("update_failed", record=record, zone=target["zone"], error=str(error))
+        heartbeat.ping(cfg, "fail", str(e))
+        metrics.inc("hetzner_ddns_runs_total", result="failure")
+        metrics.gauge("hetzner_ddns_last_run_success", 0)
+        raise
+    else:
+        if throttle.recovered("run"):
+            notify.notify(notifiers, notify.make_event("update_recovered"))
+        heartbeat.ping(cfg)
+        metrics.inc("hetzner_ddns_runs_total", result="success")
+        metrics.gauge("hetzner_ddns_last_run_success", 1)
+        metrics.gauge("hetzner_ddns_last_success_timestamp_seconds", time.time())
+        store.set_last_success()
+        output.write_output_file(cfg, store.get_published_addresses())
+    finally:
+        metrics.gauge("hetzner_ddns_run_duration_seconds", time.time() - start)
+        metrics.save(store)
+        notify.flush(notifiers)
+        metrics.export(cfg)
+        telemetry.flush()
+    return updates
+
+
+def update_targets(
+    cfg: dict,
+    store: StateStore,
+    notifiers: list[notify.Notifier],
+    throttle: notify.Throttle,
+    targets: list[dict] | None = None,
+) -> list[dict]:
+    providers = Providers(cfg, store)
+    if targets is None:
+        targets = get_targets(cfg)
+    updates = []
+    failed_target = None
+    try:
+        for i, target in enumerate(targets):
+            try:
+                with (
+                    telemetry.span("process_target", zone=target["zone"], record=target["record"]),
+                    log_context(zone=target["zone"], record=target["record"]),
+                ):
+                    process_target(cfg, providers.for_target(target), store, target, updates)
+            except Exception as e:
+                failed_target = target
+                raise TargetError(target, targets[i:]) from e
+            record = f"{target['record']}.{target['zone']}"
+            if throttle.recovered(record):
+                notify.notify(notifiers, notify.make_event("update_recovered", record=record, zone=target["zone"]))
+    finally:
+        # updates which went through before a failure still need to be recorded
+        record_updates(cfg, providers, store, notifiers, throttle, updates)
+        mqtt.publish_state(cfg, updates, failed_target)
+    return updates
+
+
+def record_updates(
+    cfg: dict,
+    providers: Providers,
+    store: StateStore,
+    notifiers: list[notify.Notifier],
+    throttle: notify.Throttle,
+    updates: list[dict],
+):
+    changes = [i for i in updates if i["old"] != i["new"]]
+    if updates:
+        store.set_published(updates)
+    metrics.inc("hetzner_ddns_records_updated_total", len(updates))
+    metrics.inc("hetzner_ddns_records_changed_total", len(changes))
+    if changes:
+        metrics.gauge("hetzner_ddns_last_change_timestamp_seconds", time.time())
+        run = store.add_run(changes)
+        snapshot_records(cfg, providers, run)
+        check_flapping(cfg, store, notifiers, throttle)
+    for i in changes:
+        hooks.run_hooks(
+            cfg.get("on_change", []),
+            {
+                "RECORD": f"{i['record']}.{i['zone']}",
+                "RECORD_TYPE": i["type"],
+                "ZONE": i["zone"],
+                "OLD_IP": i["old"],
+                "NEW_IP": i["new"],
+            },
+        )
+        notify.notify(
+            notifiers,
+            notify.make_event(
+                "record_changed",
+                record=f"{i['record']}.{i['zone']}",
+                zone=i["zone"],
+                type=i["type"],
+                old=i["old"],
+                new=i["new"],
+            ),
+        )
 
-        print(f"Updating {i_record['type']} record to {new_value}")
-        api.update_record(
-            i_record["id"],
+
+def check_flapping(cfg: dict, store: StateStore, notifiers: list[notify.Notifier], throttle: notify.Throttle):
+    """Warn if the public IP changed more often within the last hour than `flapping_threshold` allows"""
+    since = datetime.now(timezone.utc) - timedelta(hours=1)
+    changes = sum(
+        1
+        for run in store.get_history()
+        if not run.get("rollback", False) and datetime.fromisoformat(run["time"]) >= since
+    )
+    if changes <= cfg.get("flapping_threshold", 4):
+        throttle.recovered("ip_flapping")
+        return
+
+    logger.warning("The public IP changed %s times within the last hour", changes)
+    if throttle.should_notify_failure("ip_flapping"):
+        notify.notify(notifiers, notify.make_event("ip_flapping", changes=changes))
+
+
+def process_target(cfg: dict, provider: Provider, store: StateStore, target: dict, updates: list[dict]):
+    """Update the records of a target, appending each update to `updates` as soon as it went through
+
+    Updates are appended right away so that they are recorded (and can be rolled back) even if a later record of the
+    same target fails.
+    """
+    logger.info(
+        "Processing target %s.%s",
+        target["record"],
+        target["zone"],
+        extra={"record": target["record"], "zone": target["zone"]},
+    )
+    name = f"{target['record']}.{target['zone']}"
+    published = store.get_published().get(name, {})
+    # records which failed to update after others of the target went through must not be skipped
+    managed_types = store.get_managed_types(name)
+    if (
+        cfg.get("skip_unchanged", True)
+        and managed_types
+        and all(typ in published and detect_ip(cfg, store, typ) == published[typ] for typ in managed_types)
+    ):
+        # saves all api calls in the common case of nothing having changed
+        logger.info(
+            "Addresses of %s.%s are unchanged, skipping it",
+            target["record"],
+            target["zone"],
+            extra={"record": target["record"], "zone": target["zone"], "result": "unchanged"},
+        )
+        return
+
+    with telemetry.span("lookup_zone", zone=target["zone"]):
+        records = provider.find_records(target["zone"], target["record"])
+    min_update_interval = parse_duration(cfg.get("min_update_interval", 0))
+    managed_types = []
+    for i_record in records:
+        new_value = detect_ip(cfg, store, i_record["type"])
+        if new_value is None:
+            continue
+        managed_types.append(i_record["type"])
+        ip_field = "ipv4" if i_record["type"] == RecordType.A else "ipv6"
+        telemetry.set_attribute(ip_field, new_value)
+        update_log_context(**{ip_field: new_value})
+
+        last_write = store.get_last_write(i_record["id"])
+        if time.time() - last_write < min_update_interval:
+            logger.info(
+                "Not updating %s record %s.%s, it was already updated %ss ago",
+                i_record["type"],
+                i_record["name"],
+                target["zone"],
+                round(time.time() - last_write),
+                extra={
+                    "record": i_record["name"],
+                    "zone": target["zone"],
+                    "type": i_record["type"],
+                    "result": "skipped",
+                },
+            )
+            continue
+
+        start = time.monotonic()
+        with telemetry.span("update_record", zone=target["zone"], record=i_record["name"], type=i_record["type"]):
+            provider.update_record(target["zone"], i_record, new_value, provider.ttl)
+        store.set_last_write(i_record["id"])
+        events.emit(
+            "record_updated",
+            record=f"{i_record['name']}.{target['zone']}",
+            zone=target["zone"],
+            type=i_record["type"],
+            old=i_record["value"],
+            new=new_value,
+        )
+        logger.info(
+            "Updated %s record %s.%s from %s to %s",
+            i_record["type"],
+            i_record["name"],
+            target["zone"],
+            i_record["value"],
+            new_value,
+            extra={
+                "record": i_record["name"],
+                "zone": target["zone"],
+                "type": i_record["type"],
+                "old": i_record["value"],
+                "new": new_value,
+                "result": "updated",
+                "duration": round(time.monotonic() - start, 3),
+            },
+        )
+        updates.append(
             {
-                "name": i_record["name"],
-                "ttl": 60,
+                "id": i_record["id"],
+                "record": i_record["name"],
+                "zone": target["zone"],
                 "type": i_record["type"],
-                "value": new_value,
-                "zone_id": i_record["zone_id"],
+                "old": i_record["value"],
+                "new": new_value,
+                "old_ttl": i_record.get("ttl"),
+                "new_ttl": provider.ttl,
+                "provider": target.get("provider", "hetzner"),
+            }
+        )
+    store.set_managed_types(name, sorted(set(managed_types)))
+
+
+def detect_ip(cfg: dict, store: StateStore, record_type: RecordType | str) -> str | None:
+    """Detect the public address for records of the given type (or None if the type is not managed)
+
+    Detected addresses are reused for `detection_cache_ttl`, even across runs.
+    """
+    from_interface = cfg.get("detection", "kritzl_dev") == "interface"
+    match record_type:
+        case RecordType.A if from_interface:
+            detect = partial(netif.get_address, cfg["interface"], 4)
+        case RecordType.AAAA if from_interface:
+            detect = partial(netif.get_address, cfg["interface"], 6)
+        case RecordType.A:
+            detect = kritzl_dev.get_ipv4
+        case RecordType.AAAA:
+            detect = kritzl_dev.get_ipv6
+        case _:
+            return None
+    cache_ttl = parse_duration(cfg.get("detection_cache_ttl", 0))
+    cache = store.load("detected.json", {})
+    if record_type in cache and time.time() - cache[record_type]["time"] < cache_ttl:
+        return cache[record_type]["address"]
+    with telemetry.span("detect_ip", type=record_type):
+        address = detect()
+    events.emit("ip_detected", type=record_type, address=address)
+    if cache_ttl:
+        cache[record_type] = {"address": address, "time": time.time()}
+        store.save("detected.json", cache)
+    return address
+
+
+def rollback(cfg: dict, store: StateStore, run_id: int | None, record: str | None):
+    history = store.get_history()
+    if record is not None:
+        latest = {}
+        for run in history:
+            for i in run["updates"]:
+                if record in (i["record"], f"{i['record']}.{i['zone']}"):
+                    latest[i["id"]] = i
+        updates = list(latest.values())
+    elif run_id is not None:
+        runs = [run for run in history if run["id"] == run_id]
+        if not runs:
+            sys.exit(f"There is no run with id {run_id}")
+        updates = runs[0]["updates"]
+    else:
+        updates = history[-1]["updates"] if history else []
+
+    if not updates:
+        print("Nothing to roll back")
+        return
+
+    providers = Providers(cfg, store)
+    reverted = []
+    for i in updates:
+        logger.info(
+            "Restoring %s record %s.%s to %s",
+            i["type"],
+            i["record"],
+            i["zone"],
+            i["old"],
+            extra={
+                "record": i["record"],
+                "zone": i["zone"],
+                "type": i["type"],
+                "old": i["new"],
+                "new": i["old"],
+                "result": "restored",
             },
         )
+        providers.get(i.get("provider")).update_record(
+            i["zone"], {"id": i["id"], "name": i["record"], "type": i["type"]}, i["old"], i.get("old_ttl")
+        )
+        reverted.append(
+            {**i, "old": i["new"], "new": i["old"], "old_ttl": i.get("new_ttl"), "new_ttl": i.get("old_ttl")}
+        )
+    store.set_published(reverted)
+    run = store.add_run(reverted, rollback=True)
+    snapshot_records(cfg, providers, run)
+
+
+def snapshot_records(cfg: dict, providers: Providers, run: dict):
+    if "git_snapshot_dir" not in cfg:
+        return
+    summary = "\n".join(f"{i['record']}.{i['zone']} {i['type']}: {i['old']} -> {i['new']}" for i in run["updates"])
+    try:
+        commit_snapshot(
+            get_managed_records(providers, get_targets(cfg)),
+            Path(cfg["git_snapshot_dir"]),
+            f"Update records (run {run['id']})\n\n{summary}",
+        )
+    except (OSError, subprocess.CalledProcessError) as e:
+        # the records were updated nonetheless, so this must not fail the run
+        logger.warning("Could not snapshot records to %s: %s", cfg["git_snapshot_dir"], e)
+
+
+def print_history(store: StateStore, record: str | None, as_json: bool):
+    history = store.get_history()
+    if record is not None:
+        history = [
+            {**run, "updates": [i for i in run["updates"] if record in (i["record"], f"{i['record']}.{i['zone']}")]}
+            for run in history
+        ]
+        history = [run for run in history if run["updates"]]
+
+    if as_json:
+        print(json.dumps(history, indent=2))
+        return
+
+    for run in history:
+        print(f"{run['time']}  (run {run['id']})")
+        for i in run["updates"]:
+            print(f"  {i['record']}.{i['zone']} {i['type']}: {i['old']} -> {i['new']}")
 
 
 if __name__ == "__main__":
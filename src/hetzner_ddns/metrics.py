@@ -0,0 +1,204 @@
+import logging
+import socket
+import time
+from collections import deque
+from pathlib import Path
+
+import requests
+
+from hetzner_ddns.state import StateStore
+
+logger = logging.getLogger(__name__)
+
+# name -> (type, help)
+METRICS = {
+    "hetzner_ddns_runs_total": ("counter", "Number of update runs by result"),
+    "hetzner_ddns_records_updated_total": ("counter", "Number of records written to the Hetzner API"),
+    "hetzner_ddns_records_changed_total": ("counter", "Number of records whose value was changed"),
+    "hetzner_ddns_last_run_success": ("gauge", "Whether the last run was successful"),
+    "hetzner_ddns_last_run_timestamp_seconds": ("gauge", "Time of the last run"),
+    "hetzner_ddns_last_success_timestamp_seconds": ("gauge", "Time of the last successful run"),
+    "hetzner_ddns_last_change_timestamp_seconds": ("gauge", "Time a record value was last changed"),
+    "hetzner_ddns_run_duration_seconds": ("gauge", "Duration of the last run"),
+    "hetzner_ddns_api_request_duration_seconds": ("histogram", "Duration of Hetzner API requests"),
+}
+
+HISTOGRAM_BUCKETS = (0.05, 0.1, 0.25, 0.5, 1, 2.5, 5, 10)
+PERCENTILES = (0.5, 0.9, 0.99)
+# number of recent observations percentiles are calculated from
+PERCENTILE_SAMPLES = 1000
+
+# (name, sorted label items) -> value
+values: dict[tuple[str, tuple], float] = {}
+# counter values at the time they were last sent via statsd, which expects deltas
+statsd_sent: dict[tuple[str, tuple], float] = {}
+# (name, sorted label items) -> {"buckets": [...], "sum": …, "count": …, "samples": deque}
+histograms: dict[tuple[str, tuple], dict] = {}
+# observations which have not been sent via statsd yet
+statsd_timings: deque[tuple[tuple[str, tuple], float]] = deque(maxlen=PERCENTILE_SAMPLES)
+# callbacks(name, value, labels) which are notified of every histogram observation
+observers = []
+
+
+def metric_key(name: str, labels: dict) -> tuple[str, tuple]:
+    return name, tuple(sorted((k, str(v)) for k, v in labels.items()))
+
+
+def inc(name: str, value: float = 1, **labels):
+    key = metric_key(name, labels)
+    values[key] = values.get(key, 0) + value
+
+
+def gauge(name: str, value: float, **labels):
+    values[metric_key(name, labels)] = value
+
+
+def observe(name: str, value: float, **labels):
+    key = metric_key(name, labels)
+    histogram = histograms.setdefault(
+        key,
+        {"buckets": [0] * len(HISTOGRAM_BUCKETS), "sum": 0, "count": 0, "samples": deque(maxlen=PERCENTILE_SAMPLES)},
+    )
+    for i, le in enumerate(HISTOGRAM_BUCKETS):
+        if value <= le:
+            histogram["buckets"][i] += 1
+    histogram["sum"] += value
+    histogram["count"] += 1
+    histogram["samples"].append(value)
+    statsd_timings.append((key, value))
+    for observer in observers:
+        observer(name, value, labels)
+
+
+def percentile(samples, q: float) -> float:
+    samples = sorted(samples)
+    return samples[min(len(samples) - 1, int(q * len(samples)))]
+
+
+def format_prometheus_sample(name: str, labels: tuple, value: float) -> str:
+    label_str = ",".join(f'{k}="{v}"' for k, v in labels)
+    return f"{name}{{{label_str}}} {value}" if label_str else f"{name} {value}"
+
+
+def render() -> str:
+    """Render all metrics in the prometheus text exposition format"""
+    lines = []
+    for name, (typ, help_text) in METRICS.items():
+        source = histograms if typ == "histogram" else values
+        samples = sorted((labels, value) for (i_name, labels), value in source.items() if i_name == name)
+        if not samples:
+            continue
+        lines.append(f"# HELP {name} {help_text}")
+        lines.append(f"# TYPE {name} {typ}")
+        for labels, value in samples:
+            if typ != "histogram":
+                lines.append(format_prometheus_sample(name, labels, value))
+                continue
+            for le, count in zip(HISTOGRAM_BUCKETS, value["buckets"]):
+                lines.append(format_prometheus_sample(f"{name}_bucket", labels + (("le", le),), count))
+            lines.append(format_prometheus_sample(f"{name}_bucket", labels + (("le", "+Inf"),), value["count"]))
+            lines.append(format_prometheus_sample(f"{name}_sum", labels, value["sum"]))
+            lines.append(format_prometheus_sample(f"{name}_count", labels, value["count"]))
+    return "\n".join(lines) + "\n"
+
+
+def push(cfg: dict):
+    """Push all metrics to a prometheus pushgateway if one is configured"""
+    if "pushgateway_url" not in cfg:
+        return
+    job = cfg.get("pushgateway_job", "hetzner_ddns")
+    instance = cfg.get("pushgateway_instance", socket.gethostname())
+    url = f"{cfg['pushgateway_url'].rstrip('/')}/metrics/job/{job}/instance/{instance}"
+    try:
+        resp = requests.put(url, data=render(), timeout=10)
+        resp.raise_for_status()
+    except requests.RequestException as e:
+        logger.warning("Could not push metrics to %s: %s", url, e)
+
+
+def write_textfile(cfg: dict):
+    """Write all metrics into a file for the node_exporter textfile collector if one is configured"""
+    if "metrics_textfile" not in cfg:
+        return
+    path = Path(cfg["metrics_textfile"])
+    # node_exporter must never see a partially written file
+    tmp_file = path.with_name(f".{path.name}.tmp")
+    try:
+        tmp_file.write_text(render())
+        tmp_file.replace(path)
+    except OSError as e:
+        logger.warning("Could not write metrics to %s: %s", path, e)
+
+
+def format_udp_lines(fmt: str) -> list[str]:
+    lines = []
+    now_ns = time.time_ns()
+    for (name, labels), value in sorted(values.items()):
+        # telegraf understands influx style tags in statsd metric names as well
+        tags = "".join(f",{k}={v}" for k, v in labels)
+        if fmt == "influx":
+            lines.append(f"{name}{tags} value={value} {now_ns}")
+        elif METRICS[name][0] == "counter":
+            delta = value - statsd_sent.get((name, labels), 0)
+            statsd_sent[(name, labels)] = value
+            if delta:
+                lines.append(f"{name}{tags}:{delta}|c")
+        else:
+            lines.append(f"{name}{tags}:{value}|g")
+
+    if fmt == "influx":
+        for (name, labels), histogram in sorted(histograms.items()):
+            tags = "".join(f",{k}={v}" for k, v in labels)
+            fields = f"count={histogram['count']},sum={histogram['sum']}" + "".join(
+                f",p{round(q * 100)}={percentile(histogram['samples'], q)}" for q in PERCENTILES
+            )
+            lines.append(f"{name}{tags} {fields} {now_ns}")
+    else:
+        # statsd calculates percentiles itself from the individual timings
+        for (name, labels), value in statsd_timings:
+            tags = "".join(f",{k}={v}" for k, v in labels)
+            lines.append(f"{name}{tags}:{value * 1000:.3f}|ms")
+        statsd_timings.clear()
+    return lines
+
+
+def send_udp(cfg: dict):
+    """Send all metrics via udp in statsd or influx line protocol format if an address is configured"""
+    if "metrics_udp_address" not in cfg:
+        return
+    host, _, port = cfg["metrics_udp_address"].rpartition(":")
+    host = host.strip("[]")
+    try:
+        family, typ, proto, _, addr = socket.getaddrinfo(host, int(port), type=socket.SOCK_DGRAM)[0]
+        with socket.socket(family, typ, proto) as sock:
+            for line in format_udp_lines(cfg.get("metrics_udp_format", "statsd")):
+                sock.sendto(line.encode(), addr)
+    except OSError as e:
+        logger.warning("Could not send metrics to %s: %s", cfg["metrics_udp_address"], e)
+
+
+def is_persistent(name: str) -> bool:
+    return METRICS[name][0] == "counter" or name.endswith("_timestamp_seconds")
+
+
+def load(store: StateStore):
+    """Restore counters and timestamps of previous runs so that they don't start from zero again"""
+    for name, labels, value in store.load("metrics.json", []):
+        if name in METRICS and is_persistent(name):
+            key = metric_key(name, labels)
+            values[key] = value
+            # the restored values were already sent before
+            statsd_sent[key] = value
+
+
+def save(store: StateStore):
+    store.save(
+        "metrics.json",
+        [[name, dict(labels), value] for (name, labels), value in sorted(values.items()) if is_persistent(name)],
+    )
+
+
+def export(cfg: dict):
+    push(cfg)
+    write_textfile(cfg)
+    send_udp(cfg)
@@ -0,0 +1,206 @@
+"""In-memory implementation of the zones and records api of Hetzner DNS for end-to-end tests"""
+
+import argparse
+import json
+import logging
+import socket
+import threading
+import tomllib
+import uuid
+from datetime import datetime, timezone
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+from pathlib import Path
+from urllib.parse import parse_qs, urlsplit
+
+logger = logging.getLogger(__name__)
+
+API_PREFIX = "/api/v1"
+
+
+def now() -> str:
+    return datetime.now(timezone.utc).strftime("%Y-%m-%d %H:%M:%S.%f +0000 UTC")
+
+
+class MockApi:
+    """Zones and records by id, guarded by a lock since requests are handled concurrently"""
+
+    def __init__(self, token: str | None = None):
+        self.token = token
+        self.zones: dict[str, dict] = {}
+        self.records: dict[str, dict] = {}
+        self.lock = threading.Lock()
+
+    def create_zone(self, name: str, ttl: int = 86400) -> dict:
+        zone = {"id": uuid.uuid4().hex, "name": name, "ttl": ttl, "status": "verified", "created": now()}
+        zone["modified"] = zone["created"]
+        self.zones[zone["id"]] = zone
+        return zone
+
+    def zone_by_name(self, name: str) -> dict:
+        return next((i for i in self.zones.values() if i["name"] == name), None) or self.create_zone(name)
+
+    def create_record(self, data: dict, record_id: str | None = None) -> dict:
+        if data.get("zone_id") not in self.zones:
+            raise KeyError("zone not found")
+        record = {k: data[k] for k in ("zone_id", "name", "type", "value", "ttl") if k in data}
+        record.update(id=record_id or uuid.uuid4().hex, created=now(), modified=now())
+        self.records[record["id"]] = record
+        return record
+
+    def load(self, path: Path):
+        """Add the records of a backup file written by `hetzner_ddns backup`, creating their zones as needed"""
+        with open(path, "rb") as f:
+            data = tomllib.load(f) if path.suffix == ".toml" else json.load(f)
+        for i in data["records"]:
+            # the ids of the backup are kept so that e.g. the cached zones and writes of state directories stay valid
+            self.create_record({**i, "zone_id": self.zone_by_name(i["zone"])["id"]}, i.get("id"))
+
+
+def paginate(items: list[dict], key: str, query: dict) -> dict:
+    page = int(query.get("page", ["1"])[0])
+    per_page = int(query.get("per_page", ["100"])[0])
+    return {
+        key: items[(page - 1) * per_page : page * per_page],
+        "meta": {
+            "pagination": {
+                "page": page,
+                "per_page": per_page,
+                "last_page": max(1, -(-len(items) // per_page)),
+                "total_entries": len(items),
+            }
+        },
+    }
+
+
+class MockApiHandler(BaseHTTPRequestHandler):
+    server: "MockApiServer"
+
+    def log_message(self, format: str, *args):
+        logger.debug(format, *args)
+
+    def send(self, status: int, data: dict | None = None):
+        body = json.dumps(data).encode() if data is not None else b""
+        self.send_response(status)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(body)))
+        self.end_headers()
+        self.wfile.write(body)
+
+    def send_error_json(self, status: int, message: str):
+        self.send(status, {"error": {"message": message, "code": status}})
+
+    def body(self) -> dict:
+        return json.loads(self.rfile.read(int(self.headers.get("Content-Length") or 0)) or b"{}")
+
+    def handle_any(self, method: str):
+        url = urlsplit(self.path)
+        if method == "GET" and url.path == "/ip":
+            # lets the detection be pointed at the mock server as well
+            body = self.client_address[0].encode()
+            self.send_response(200)
+            self.send_header("Content-Length", str(len(body)))
+            self.end_headers()
+            self.wfile.write(body)
+            return
+        api = self.server.api
+        token = self.headers.get("Auth-API-Token")
+        if not token or (api.token is not None and token != api.token):
+            return self.send_error_json(401, "invalid authentication credentials")
+        if not url.path.startswith(API_PREFIX):
+            return self.send_error_json(404, "not found")
+        path = url.path.removeprefix(API_PREFIX)
+        with api.lock:
+            try:
+                self.route(api, method, path, parse_qs(url.query))
+            except KeyError as e:
+                self.send_error_json(404, str(e.args[0]))
+            except (ValueError, TypeError) as e:
+                self.send_error_json(422, str(e))
+
+    def route(self, api: MockApi, method: str, path: str, query: dict):
+        match method, path.rstrip("/").split("/")[1:]:
+            case "GET", ["zones"]:
+                zones = list(api.zones.values())
+                if "name" in query:
+                    zones = [i for i in zones if i["name"] == query["name"][0]]
+                if "search_name" in query:
+                    zones = [i for i in zones if query["search_name"][0] in i["name"]]
+                self.send(200, paginate(zones, "zones", query))
+            case "POST", ["zones"]:
+                data = self.body()
+                self.send(200, {"zone": api.create_zone(data["name"], data.get("ttl", 86400))})
+            case "GET", ["zones", zone_id]:
+                self.send(200, {"zone": api.zones[zone_id]})
+            case "DELETE", ["zones", zone_id]:
+                del api.zones[zone_id]
+                api.records = {k: v for k, v in api.records.items() if v["zone_id"] != zone_id}
+                self.send(200)
+            case "GET", ["records"]:
+                records = [i for i in api.records.values() if i["zone_id"] == query.get("zone_id", [None])[0]]
+                self.send(200, paginate(records, "records", query))
+            case "POST", ["records"]:
+                self.send(200, {"record": api.create_record(self.body())})
+            case "POST", ["records", "bulk"]:
+                records = [api.create_record(i) for i in self.body()["records"]]
+                self.send(200, {"records": records, "valid_records": records, "invalid_records": []})
+            case "GET", ["records", record_id]:
+                self.send(200, {"record": api.records[record_id]})
+            case "PUT", ["records", record_id]:
+                record = api.records[record_id]
+                data = self.body()
+                record.update({k: data[k] for k in ("zone_id", "name", "type", "value", "ttl") if k in data})
+                record["modified"] = now()
+                self.send(200, {"record": record})
+            case "DELETE", ["records", record_id]:
+                del api.records[record_id]
+                self.send(200)
+            case _:
+                self.send_error_json(404, "not found")
+
+    def do_GET(self):
+        self.handle_any("GET")
+
+    def do_POST(self):
+        self.handle_any("POST")
+
+    def do_PUT(self):
+        self.handle_any("PUT")
+
+    def do_DELETE(self):
+        self.handle_any("DELETE")
+
+
+class MockApiServer(ThreadingHTTPServer):
+    def __init__(self, address: tuple[str, int], api: MockApi):
+        if ":" in address[0]:
+            self.address_family = socket.AF_INET6
+        super().__init__(address, MockApiHandler)
+        self.api = api
+
+
+def main():
+    argp = argparse.ArgumentParser(
+        prog="hetzner-ddns-mock-server",
+        description="Serve an in-memory Hetzner DNS api, e.g. to run hetzner_ddns against it with api_url",
+    )
+    argp.add_argument("--listen", default="127.0.0.1:8080", help="Address to listen on (default: 127.0.0.1:8080)")
+    argp.add_argument("--token", help="Only accept this api token (default: any)")
+    argp.add_argument("records", nargs="*", type=Path, help="Backup files (.json or .toml) with the initial records")
+    argp.add_argument("-v", "--verbose", action="store_true", help="Log every request")
+    args = argp.parse_args()
+    logging.basicConfig(level=logging.DEBUG if args.verbose else logging.INFO, format="%(asctime)s %(message)s")
+
+    api = MockApi(args.token)
+    for path in args.records:
+        api.load(path)
+    host, _, port = args.listen.rpartition(":")
+    server = MockApiServer((host.strip("[]"), int(port)), api)
+    logger.info("Serving the mock api at http://%s%s", args.listen, API_PREFIX)
+    try:
+        server.serve_forever()
+    except KeyboardInterrupt:
+        pass
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,130 @@
+import json
+import logging
+import re
+import socket
+import ssl
+import struct
+from datetime import datetime, timezone
+
+from hetzner_dns_api import RecordType
+
+from hetzner_ddns.logs import register_secret
+
+logger = logging.getLogger(__name__)
+
+
+def encode_length(n: int) -> bytes:
+    result = bytearray()
+    while True:
+        byte, n = n % 128, n // 128
+        result.append(byte | (0x80 if n else 0))
+        if not n:
+            return bytes(result)
+
+
+def encode_str(s: str) -> bytes:
+    data = s.encode()
+    return struct.pack("!H", len(data)) + data
+
+
+class MqttClient:
+    """Minimal MQTT 3.1.1 client which can only publish messages with QoS 0"""
+
+    def __init__(self, cfg: dict, client_id: str):
+        tls = cfg.get("tls", False)
+        self.sock = socket.create_connection((cfg["host"], cfg.get("port", 8883 if tls else 1883)), timeout=30)
+        if tls:
+            self.sock = ssl.create_default_context().wrap_socket(self.sock, server_hostname=cfg["host"])
+
+        flags = 0x02  # clean session
+        payload = encode_str(client_id)
+        if "username" in cfg:
+            flags |= 0x80
+            payload += encode_str(cfg["username"])
+        if "password" in cfg:
+            flags |= 0x40
+            payload += encode_str(cfg["password"])
+        self.send_packet(0x10, encode_str("MQTT") + bytes([4, flags]) + struct.pack("!H", 60) + payload)
+
+        connack = self.sock.recv(4)
+        if len(connack) < 4 or connack[0] != 0x20:
+            raise RuntimeError("mqtt broker did not acknowledge the connection")
+        if connack[3] != 0:
+            raise RuntimeError(f"mqtt broker refused the connection with return code {connack[3]}")
+
+    def send_packet(self, header: int, body: bytes):
+        self.sock.sendall(bytes([header]) + encode_length(len(body)) + body)
+
+    def publish(self, topic: str, payload: str, retain: bool = True):
+        self.send_packet(0x30 | (0x01 if retain else 0), encode_str(topic) + payload.encode())
+
+    def close(self):
+        try:
+            self.send_packet(0xE0, b"")
+        except OSError:
+            # the broker may have dropped the connection already, which the caller has been told about
+            pass
+        finally:
+            self.sock.close()
+
+
+def slug(value: str) -> str:
+    return re.sub(r"[^a-zA-Z0-9_-]", "_", value)
+
+
+def publish_state(cfg: dict, updates: list[dict], failed_target: dict | None):
+    """Publish detected addresses and per-record status, including Home Assistant discovery configs"""
+    if "mqtt" not in cfg:
+        return
+    mqtt_cfg = cfg["mqtt"]
+    if "password" in mqtt_cfg:
+        register_secret(mqtt_cfg["password"])
+    hostname = socket.gethostname()
+    node_id = slug(f"hetzner_ddns_{hostname}")
+    prefix = mqtt_cfg.get("topic_prefix", f"hetzner_ddns/{slug(hostname)}")
+    now = datetime.now(timezone.utc).isoformat(timespec="seconds")
+
+    # topic suffix -> (sensor name, value, device class)
+    states = {
+        "status": ("Status", "failed" if failed_target else "ok", None),
+        "last_run": ("Last run", now, "timestamp"),
+    }
+    for i in updates:
+        if i["type"] == RecordType.A:
+            states["ipv4"] = ("Public IPv4", i["new"], None)
+        else:
+            states["ipv6"] = ("Public IPv6", i["new"], None)
+        record = f"{i['record']}.{i['zone']}"
+        states[f"{slug(record)}/status"] = (f"{record} status", "ok", None)
+        states[f"{slug(record)}/{i['type'].lower()}"] = (f"{record} {i['type']}", i["new"], None)
+        if i["old"] != i["new"]:
+            states[f"{slug(record)}/last_update"] = (f"{record} last update", now, "timestamp")
+            states["last_change"] = ("Last change", now, "timestamp")
+    if failed_target:
+        record = f"{failed_target['record']}.{failed_target['zone']}"
+        states[f"{slug(record)}/status"] = (f"{record} status", "failed", None)
+
+    try:
+        client = MqttClient(mqtt_cfg, node_id)
+    except (OSError, RuntimeError) as e:
+        logger.warning("Could not connect to mqtt broker: %s", e)
+        return
+    try:
+        for suffix, (name, value, device_class) in states.items():
+            client.publish(f"{prefix}/{suffix}", value)
+            if mqtt_cfg.get("discovery", True):
+                object_id = slug(suffix)
+                config = {
+                    "name": name,
+                    "state_topic": f"{prefix}/{suffix}",
+                    "unique_id": f"{node_id}_{object_id}",
+                    "device": {"identifiers": [node_id], "name": f"hetzner_ddns on {hostname}"},
+                }
+                if device_class:
+                    config["device_class"] = device_class
+                discovery_prefix = mqtt_cfg.get("discovery_prefix", "homeassistant")
+                client.publish(f"{discovery_prefix}/sensor/{node_id}/{object_id}/config", json.dumps(config))
+    except OSError as e:
+        logger.warning("Could not publish to mqtt broker: %s", e)
+    finally:
+        client.close()
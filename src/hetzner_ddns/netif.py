@@ -0,0 +1,97 @@
+import ctypes
+import ctypes.util
+import ipaddress
+import os
+import socket
+import subprocess
+import sys
+from pathlib import Path
+
+IPAddress = ipaddress.IPv4Address | ipaddress.IPv6Address
+
+# flags of /proc/net/if_inet6 (IFA_F_* from linux/if_addr.h)
+LINUX_IPV6_FLAGS = {0x01: "temporary", 0x08: "duplicated", 0x20: "deprecated", 0x40: "tentative"}
+# addresses with these flags must not be published
+UNUSABLE_IPV6_FLAGS = {"deprecated", "tentative", "duplicated", "detached"}
+
+
+class Ifaddrs(ctypes.Structure):
+    pass
+
+
+# the layout is the same on linux and the BSDs
+Ifaddrs._fields_ = [
+    ("ifa_next", ctypes.POINTER(Ifaddrs)),
+    ("ifa_name", ctypes.c_char_p),
+    ("ifa_flags", ctypes.c_uint),
+    ("ifa_addr", ctypes.c_void_p),
+    ("ifa_netmask", ctypes.c_void_p),
+    ("ifa_dstaddr", ctypes.c_void_p),
+    ("ifa_data", ctypes.c_void_p),
+]
+
+
+def sockaddr_family(addr: int) -> int:
+    if sys.platform.startswith("linux"):
+        return int.from_bytes(ctypes.string_at(addr, 2), sys.byteorder)
+    # the BSDs store the length of the sockaddr in the first byte
+    return ctypes.string_at(addr, 2)[1]
+
+
+def interface_addresses(interface: str) -> list[IPAddress]:
+    """All addresses assigned to a network interface, as returned by getifaddrs(3)"""
+    libc = ctypes.CDLL(ctypes.util.find_library("c"), use_errno=True)
+    ifap = ctypes.POINTER(Ifaddrs)()
+    if libc.getifaddrs(ctypes.byref(ifap)) != 0:
+        errno = ctypes.get_errno()
+        raise OSError(errno, os.strerror(errno))
+    addresses = []
+    try:
+        ifa = ifap
+        while ifa:
+            entry = ifa.contents
+            if entry.ifa_name.decode() == interface and entry.ifa_addr:
+                family = sockaddr_family(entry.ifa_addr)
+                # offsets of sin_addr and sin6_addr
+                if family == socket.AF_INET:
+                    addresses.append(ipaddress.IPv4Address(ctypes.string_at(entry.ifa_addr, 8)[4:]))
+                elif family == socket.AF_INET6:
+                    addresses.append(ipaddress.IPv6Address(ctypes.string_at(entry.ifa_addr, 24)[8:]))
+            ifa = entry.ifa_next
+    finally:
+        libc.freeifaddrs(ifap)
+    return addresses
+
+
+def ipv6_flags(interface: str) -> dict[ipaddress.IPv6Address, set[str]]:
+    """Flags like `deprecated` or `temporary` of the ipv6 addresses of a network interface"""
+    flags = {}
+    if sys.platform.startswith("linux"):
+        # address, interface index, prefix length, scope, flags, interface name
+        for line in Path("/proc/net/if_inet6").read_text().splitlines():
+            address, _, _, _, value, name = line.split()
+            if name == interface:
+                flags[ipaddress.IPv6Address(int(address, 16))] = {
+                    flag for bit, flag in LINUX_IPV6_FLAGS.items() if int(value, 16) & bit
+                }
+    else:
+        # e.g. "inet6 2001:db8::1 prefixlen 64 deprecated autoconf temporary pltime 0 vltime 3600"
+        output = subprocess.run(["ifconfig", interface, "inet6"], capture_output=True, text=True, check=True).stdout
+        for line in output.splitlines():
+            fields = line.split()
+            if fields and fields[0] == "inet6":
+                flags[ipaddress.IPv6Address(fields[1].split("%")[0])] = set(fields[2:])
+    return flags
+
+
+def get_address(interface: str, version: int) -> str:
+    """The public address of the given ip version assigned to a network interface"""
+    candidates = [i for i in interface_addresses(interface) if i.version == version and i.is_global]
+    if version == 6:
+        flags = ipv6_flags(interface)
+        candidates = [i for i in candidates if not flags.get(i, set()) & UNUSABLE_IPV6_FLAGS]
+        # stable addresses are preferred over temporary privacy addresses which change regularly
+        candidates.sort(key=lambda i: "temporary" in flags.get(i, set()))
+    if not candidates:
+        raise RuntimeError(f"Interface {interface} has no usable public IPv{version} address")
+    return str(candidates[0])
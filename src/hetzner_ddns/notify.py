@@ -0,0 +1,490 @@
+import base64
+import html
+import json
+import logging
+import smtplib
+import socket
+import ssl
+import subprocess
+import time
+import uuid
+from datetime import datetime, timezone
+from email.message import EmailMessage
+from string import Template
+from urllib.parse import quote
+from xml.sax.saxutils import escape, quoteattr
+
+import requests
+from requests.structures import CaseInsensitiveDict
+
+from hetzner_ddns.logs import register_secret
+from hetzner_ddns.state import StateStore
+
+logger = logging.getLogger(__name__)
+
+EVENT_FIELDS = ("event", "record", "zone", "type", "old", "new", "error", "changes")
+# event -> severity, which notifiers map to priorities, colors and the like
+SEVERITIES = {
+    "record_changed": "info",
+    "update_failed": "error",
+    "update_recovered": "success",
+    "ip_flapping": "warning",
+}
+
+
+def make_event(event: str, **fields) -> dict:
+    """Build a notification event
+
+    `event` is one of the keys of `SEVERITIES` and the remaining fields are taken from `EVENT_FIELDS`.
+    Fields which do not apply to an event are set to None.
+    """
+    return {
+        **{field: None for field in EVENT_FIELDS},
+        **fields,
+        "event": event,
+        "host": socket.gethostname(),
+        "time": datetime.now(timezone.utc).isoformat(timespec="seconds"),
+    }
+
+
+def describe(event: dict) -> tuple[str, str]:
+    """Human readable title and message of an event"""
+    match event["event"]:
+        case "record_changed":
+            return (
+                f"{event['record']} changed",
+                f"{event['type']} record {event['record']} changed from {event['old']} to {event['new']}",
+            )
+        case "ip_flapping":
+            return (
+                "Public IP is flapping",
+                f"The public IP of {event['host']} changed {event['changes']} times within the last hour, "
+                "which usually indicates a problem with the modem or ISP",
+            )
+        case "update_recovered":
+            target = f" {event['record']}" if event["record"] else ""
+            return f"Updating{target} recovered", f"Updating{target} on {event['host']} works again"
+        case _:
+            target = f" {event['record']}" if event["record"] else ""
+            return f"Updating{target} failed", f"Updating{target} on {event['host']} failed: {event['error']}"
+
+
+class Notifier:
+    def __init__(self, cfg: dict):
+        self.cfg = cfg
+        self.events = cfg.get("events", list(SEVERITIES))
+
+    def send(self, event: dict):
+        raise NotImplementedError
+
+    def flush(self):
+        """Called at the end of every run for notifiers which collect events instead of sending them directly"""
+
+
+class WebhookNotifier(Notifier):
+    """Sends the event as json, or as `body` template with `$field` placeholders, to a (templated) url"""
+
+    def send(self, event: dict):
+        fields = {k: "" if v is None else str(v) for k, v in event.items()}
+        url = Template(self.cfg["url"]).safe_substitute({k: quote(v, safe="") for k, v in fields.items()})
+        headers = CaseInsensitiveDict({"Content-Type": "application/json", **self.cfg.get("headers", {})})
+        if "body" in self.cfg:
+            if "json" in headers["Content-Type"]:
+                # values are inserted into json strings, which e.g. quotes in error messages would break otherwise
+                fields = {k: json.dumps(v)[1:-1] for k, v in fields.items()}
+            data = Template(self.cfg["body"]).safe_substitute(fields).encode()
+        else:
+            data = json.dumps(event).encode()
+        resp = requests.request(self.cfg.get("method", "POST"), url, data=data, headers=headers, timeout=10)
+        resp.raise_for_status()
+
+
+class NtfyNotifier(Notifier):
+    """Publishes to an ntfy topic given as `url`, e.g. `https://ntfy.sh/<topic>`"""
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        severity = SEVERITIES[event["event"]]
+        headers = {
+            "Title": title,
+            "Tags": {"error": "rotating_light", "warning": "warning", "success": "white_check_mark"}.get(
+                severity, "globe_with_meridians"
+            ),
+            "Priority": "high" if severity in ("error", "warning") else "default",
+        }
+        if "token" in self.cfg:
+            headers["Authorization"] = f"Bearer {self.cfg['token']}"
+        auth = (self.cfg["username"], self.cfg["password"]) if "username" in self.cfg else None
+        resp = requests.post(self.cfg["url"], data=message.encode(), headers=headers, auth=auth, timeout=10)
+        resp.raise_for_status()
+
+
+class TelegramNotifier(Notifier):
+    """Messages a chat through a telegram bot"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        register_secret(cfg["bot_token"])
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        if event["event"] == "record_changed":
+            message = (
+                f"{event['type']} record <code>{html.escape(event['record'])}</code> changed\n"
+                f"<code>{html.escape(event['old'])}</code> → <code>{html.escape(event['new'])}</code>"
+            )
+        else:
+            message = html.escape(message)
+        data = {"chat_id": self.cfg["chat_id"], "text": f"<b>{html.escape(title)}</b>\n{message}", "parse_mode": "HTML"}
+        resp = requests.post(f"https://api.telegram.org/bot{self.cfg['bot_token']}/sendMessage", json=data, timeout=10)
+        resp.raise_for_status()
+
+
+class MatrixNotifier(Notifier):
+    """Sends notices into a matrix room using the access token of a bot account"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        register_secret(cfg["access_token"])
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        url = (
+            f"{self.cfg['homeserver'].rstrip('/')}/_matrix/client/v3/rooms/{quote(self.cfg['room_id'], safe='')}"
+            f"/send/m.room.message/{uuid.uuid4()}"
+        )
+        data = {
+            "msgtype": "m.notice",
+            "body": f"{title}\n{message}",
+            "format": "org.matrix.custom.html",
+            "formatted_body": f"<b>{html.escape(title)}</b><br>{html.escape(message)}",
+        }
+        headers = {"Authorization": f"Bearer {self.cfg['access_token']}"}
+        resp = requests.put(url, json=data, headers=headers, timeout=10)
+        resp.raise_for_status()
+
+
+class EmailNotifier(Notifier):
+    """Collects all events of a run and sends them as one digest email via smtp"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        self.pending = []
+        if "password" in cfg:
+            register_secret(cfg["password"])
+
+    def send(self, event: dict):
+        self.pending.append(event)
+
+    def flush(self):
+        if not self.pending:
+            return
+        events, self.pending = self.pending, []
+        changes = sum(1 for i in events if i["event"] == "record_changed")
+        failures = sum(1 for i in events if i["event"] == "update_failed")
+        recoveries = sum(1 for i in events if i["event"] == "update_recovered")
+        summary = []
+        if changes:
+            summary.append(f"{changes} records changed")
+        if failures:
+            summary.append(f"{failures} updates failed")
+        if recoveries:
+            summary.append(f"{recoveries} updates recovered")
+        if any(i["event"] == "ip_flapping" for i in events):
+            summary.append("public IP is flapping")
+
+        msg = EmailMessage()
+        msg["Subject"] = f"hetzner_ddns on {events[0]['host']}: {', '.join(summary)}"
+        msg["From"] = self.cfg["from"]
+        msg["To"] = ", ".join(self.cfg["to"])
+        msg.set_content("\n".join(f"{i['time']}  {describe(i)[1]}" for i in events) + "\n")
+
+        port = self.cfg.get("port", 465 if self.cfg.get("tls", False) else 587)
+        smtp_cls = smtplib.SMTP_SSL if self.cfg.get("tls", False) else smtplib.SMTP
+        with smtp_cls(self.cfg["host"], port, timeout=30) as smtp:
+            if not self.cfg.get("tls", False) and self.cfg.get("starttls", True):
+                smtp.starttls()
+            if "username" in self.cfg:
+                smtp.login(self.cfg["username"], self.cfg["password"])
+            smtp.send_message(msg)
+
+
+class DiscordNotifier(Notifier):
+    """Posts an embed through a discord webhook"""
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        embed = {"title": title, "timestamp": event["time"]}
+        if event["event"] == "record_changed":
+            embed["color"] = 0x2ECC71
+            embed["fields"] = [
+                {"name": "Record", "value": f"`{event['record']}` ({event['type']})", "inline": False},
+                {"name": "Old address", "value": f"`{event['old']}`", "inline": True},
+                {"name": "New address", "value": f"`{event['new']}`", "inline": True},
+            ]
+        else:
+            embed["color"] = {"error": 0xE74C3C, "warning": 0xE67E22}.get(SEVERITIES[event["event"]], 0x3498DB)
+            embed["description"] = message
+        embed["footer"] = {"text": event["host"]}
+        data = {"username": self.cfg.get("username", "hetzner_ddns"), "embeds": [embed]}
+        resp = requests.post(self.cfg["url"], json=data, timeout=10)
+        resp.raise_for_status()
+
+
+class SlackNotifier(Notifier):
+    """Posts a message through a slack incoming webhook"""
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        # slack's mrkdwn only requires these characters to be escaped
+        message = message.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
+        icon = {
+            "info": ":globe_with_meridians:",
+            "error": ":rotating_light:",
+            "warning": ":warning:",
+            "success": ":white_check_mark:",
+        }[SEVERITIES[event["event"]]]
+        data = {
+            "text": f"{title}: {message}",
+            "blocks": [{"type": "section", "text": {"type": "mrkdwn", "text": f"{icon} *{title}*\n{message}"}}],
+        }
+        resp = requests.post(self.cfg["url"], json=data, timeout=10)
+        resp.raise_for_status()
+
+
+class GotifyNotifier(Notifier):
+    """Sends a message to a gotify server using an application token"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        register_secret(cfg["token"])
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        if SEVERITIES[event["event"]] in ("error", "warning"):
+            priority = self.cfg.get("failure_priority", 8)
+        else:
+            priority = self.cfg.get("priority", 5)
+        resp = requests.post(
+            f"{self.cfg['url'].rstrip('/')}/message",
+            json={"title": title, "message": message, "priority": priority},
+            headers={"X-Gotify-Key": self.cfg["token"]},
+            timeout=10,
+        )
+        resp.raise_for_status()
+
+
+class PushoverNotifier(Notifier):
+    """Sends a message via pushover with separate priorities for changes and failures"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        register_secret(cfg["app_token"])
+        register_secret(cfg["user_key"])
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        if SEVERITIES[event["event"]] in ("error", "warning"):
+            priority = self.cfg.get("failure_priority", 1)
+        else:
+            priority = self.cfg.get("priority", 0)
+        data = {
+            "token": self.cfg["app_token"],
+            "user": self.cfg["user_key"],
+            "title": title,
+            "message": message,
+            "priority": priority,
+        }
+        if priority == 2:
+            # emergency priority is repeated until acknowledged
+            data["retry"] = self.cfg.get("emergency_retry", 60)
+            data["expire"] = self.cfg.get("emergency_expire", 3600)
+        resp = requests.post("https://api.pushover.net/1/messages.json", data=data, timeout=10)
+        resp.raise_for_status()
+
+
+class AppriseNotifier(Notifier):
+    """Sends to any number of apprise urls using the apprise library or, if it is not installed, the apprise cli"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        for url in cfg["urls"]:
+            register_secret(url)
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        # apprise calls errors failures but otherwise uses the same notification types
+        notify_type = {"error": "failure"}.get(SEVERITIES[event["event"]], SEVERITIES[event["event"]])
+        try:
+            import apprise
+        except ImportError:
+            subprocess.run(
+                ["apprise", "-n", notify_type, "-t", title, "-b", message, *self.cfg["urls"]],
+                check=True,
+                capture_output=True,
+                timeout=60,
+            )
+            return
+
+        apobj = apprise.Apprise()
+        for url in self.cfg["urls"]:
+            apobj.add(url)
+        if not apobj.notify(title=title, body=message, notify_type=apprise.NotifyType(notify_type)):
+            raise RuntimeError("apprise could not deliver the notification to all urls")
+
+
+class XmppNotifier(Notifier):
+    """Sends a chat message from `jid` to `recipient` with a minimal xmpp client (STARTTLS, SASL PLAIN, bind)"""
+
+    def __init__(self, cfg: dict):
+        super().__init__(cfg)
+        register_secret(cfg["password"])
+
+    def send(self, event: dict):
+        title, message = describe(event)
+        user, _, domain = self.cfg["jid"].partition("@")
+        domain = domain.split("/")[0]
+        sock = socket.create_connection((self.cfg.get("host", domain), self.cfg.get("port", 5222)), timeout=30)
+        try:
+            if "urn:ietf:params:xml:ns:xmpp-tls" not in self.open_stream(sock, domain):
+                raise RuntimeError("xmpp server does not support STARTTLS")
+            sock.sendall(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")
+            if "<proceed" not in self.read_until(sock, "<proceed", "<failure"):
+                raise RuntimeError("xmpp server refused STARTTLS")
+            sock = ssl.create_default_context().wrap_socket(sock, server_hostname=domain)
+
+            self.open_stream(sock, domain)
+            credentials = base64.b64encode(f"\0{user}\0{self.cfg['password']}".encode()).decode()
+            sock.sendall(
+                f"<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>{credentials}</auth>".encode()
+            )
+            if "<success" not in self.read_until(sock, "<success", "<failure"):
+                raise RuntimeError("xmpp authentication failed")
+
+            self.open_stream(sock, domain)
+            sock.sendall(
+                b"<iq type='set' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>"
+                b"<resource>hetzner_ddns</resource></bind></iq>"
+            )
+            response = self.read_until(sock, "</iq>")
+            if "type='error'" in response or 'type="error"' in response:
+                raise RuntimeError("xmpp resource binding failed")
+
+            body = escape(f"{title}\n{message}")
+            sock.sendall(
+                f"<message to={quoteattr(self.cfg['recipient'])} type='chat'><body>{body}</body></message>"
+                "</stream:stream>".encode()
+            )
+            # give the server the chance to process the message before the connection is closed
+            self.read_until(sock, "</stream:stream>")
+        finally:
+            sock.close()
+
+    def open_stream(self, sock: socket.socket, domain: str) -> str:
+        sock.sendall(
+            f"<?xml version='1.0'?><stream:stream to={quoteattr(domain)} version='1.0' xmlns='jabber:client' "
+            f"xmlns:stream='http://etherx.jabber.org/streams'>".encode()
+        )
+        return self.read_until(sock, "</stream:features>")
+
+    @staticmethod
+    def read_until(sock: socket.socket, *markers: str) -> str:
+        data = ""
+        while not any(i in data for i in markers):
+            chunk = sock.recv(4096)
+            if not chunk:
+                break
+            data += chunk.decode(errors="replace")
+        return data
+
+
+class GrafanaNotifier(Notifier):
+    """Creates a grafana annotation so that events show up on dashboards (by default only for changed records)"""
+
+    def __init__(self, cfg: dict):
+        super().__init__({"events": ["record_changed"], **cfg})
+        register_secret(cfg["token"])
+
+    def send(self, event: dict):
+        _, message = describe(event)
+        tags = ["hetzner_ddns", event["event"], *self.cfg.get("tags", [])]
+        if event["record"]:
+            tags.append(event["record"])
+        data = {
+            "time": int(datetime.fromisoformat(event["time"]).timestamp() * 1000),
+            "tags": tags,
+            "text": message,
+        }
+        if "dashboard_uid" in self.cfg:
+            data["dashboardUID"] = self.cfg["dashboard_uid"]
+        resp = requests.post(
+            f"{self.cfg['url'].rstrip('/')}/api/annotations",
+            json=data,
+            headers={"Authorization": f"Bearer {self.cfg['token']}"},
+            timeout=10,
+        )
+        resp.raise_for_status()
+
+
+NOTIFIERS = {
+    "webhook": WebhookNotifier,
+    "ntfy": NtfyNotifier,
+    "telegram": TelegramNotifier,
+    "matrix": MatrixNotifier,
+    "email": EmailNotifier,
+    "discord": DiscordNotifier,
+    "slack": SlackNotifier,
+    "gotify": GotifyNotifier,
+    "pushover": PushoverNotifier,
+    "apprise": AppriseNotifier,
+    "xmpp": XmppNotifier,
+    "grafana": GrafanaNotifier,
+}
+
+
+class Throttle:
+    """Limits failure notifications to one per `interval` seconds and target and detects recoveries"""
+
+    def __init__(self, store: StateStore, interval: float):
+        self.store = store
+        self.interval = interval
+        self.failing = store.load("failing.json", {})
+
+    def should_notify_failure(self, key: str) -> bool:
+        now = time.time()
+        entry = self.failing.setdefault(key, {"since": now, "notified": 0})
+        notify_now = now - entry["notified"] >= self.interval
+        if notify_now:
+            entry["notified"] = now
+        self.store.save("failing.json", self.failing)
+        return notify_now
+
+    def recovered(self, key: str) -> bool:
+        """Mark the target as working and return whether it was failing before"""
+        if key not in self.failing:
+            return False
+        del self.failing[key]
+        self.store.save("failing.json", self.failing)
+        return True
+
+
+def create_notifiers(cfg: dict) -> list[Notifier]:
+    return [NOTIFIERS[i["type"]](i) for i in cfg.get("notifiers", [])]
+
+
+def notify(notifiers: list[Notifier], event: dict):
+    for notifier in notifiers:
+        if event["event"] not in notifier.events:
+            continue
+        try:
+            notifier.send(event)
+        except Exception as e:
+            logger.warning("Could not send %s notification: %s", notifier.cfg["type"], e)
+
+
+def flush(notifiers: list[Notifier]):
+    for notifier in notifiers:
+        try:
+            notifier.flush()
+        except Exception as e:
+            logger.warning("Could not send %s notification: %s", notifier.cfg["type"], e)
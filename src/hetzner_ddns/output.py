@@ -0,0 +1,31 @@
+import logging
+import socket
+from pathlib import Path
+from string import Template
+
+from hetzner_dns_api import RecordType
+
+logger = logging.getLogger(__name__)
+
+DEFAULT_TEMPLATE = "$ipv4\n$ipv6\n"
+
+
+def write_output_file(cfg: dict, addresses: dict[str, str]):
+    """Render the detected addresses into `output_file` if one is configured and its content would change"""
+    if "output_file" not in cfg:
+        return
+    path = Path(cfg["output_file"])
+    fields = {"ipv4": "", "ipv6": "", "hostname": socket.gethostname()}
+    for typ, address in addresses.items():
+        fields["ipv4" if typ == RecordType.A else "ipv6"] = address
+    content = Template(cfg.get("output_template", DEFAULT_TEMPLATE)).safe_substitute(fields)
+    try:
+        if path.exists() and path.read_text() == content:
+            return
+        # readers must never see a partially written file
+        tmp_file = path.with_name(f".{path.name}.tmp")
+        tmp_file.write_text(content)
+        tmp_file.replace(path)
+        logger.info("Wrote current addresses to %s", path)
+    except OSError as e:
+        logger.warning("Could not write %s: %s", path, e)
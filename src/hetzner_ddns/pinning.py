@@ -0,0 +1,69 @@
+import base64
+import hashlib
+import ssl
+
+from requests.adapters import HTTPAdapter
+from urllib3 import PoolManager
+
+
+class PinMismatchError(ssl.SSLError):
+    """The public key of the server matches none of the configured pins"""
+
+
+def der_element(data: bytes, offset: int) -> tuple[int, int]:
+    """The start of the content and the end of the DER element at `offset`"""
+    length = data[offset + 1]
+    offset += 2
+    if length & 0x80:
+        size = length & 0x7F
+        length = int.from_bytes(data[offset : offset + size], "big")
+        offset += size
+    return offset, offset + length
+
+
+def spki_pin(cert: bytes) -> str:
+    """The pin of a DER encoded certificate as `sha256/<base64 of the hashed SubjectPublicKeyInfo>`
+
+    This is the format used by curl's `--pinnedpubkey` and can be computed with
+    `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+    """
+    tbs, _ = der_element(cert, 0)
+    offset, _ = der_element(cert, tbs)
+    # the tbsCertificate starts with an optional version, the serial, signature, issuer, validity and subject
+    for _ in range(6 if cert[offset] == 0xA0 else 5):
+        _, offset = der_element(cert, offset)
+    _, end = der_element(cert, offset)
+    return "sha256/" + base64.b64encode(hashlib.sha256(cert[offset:end]).digest()).decode()
+
+
+def pin_pool_manager(manager: PoolManager, pins: set[str]):
+    """Make https connections of the manager check the server's public key right after the handshake"""
+    base = manager.pool_classes_by_scheme["https"]
+
+    def connect(self):
+        super(connection_cls, self).connect()
+        pin = spki_pin(self.sock.getpeercert(binary_form=True))
+        if pin not in pins:
+            self.close()
+            raise PinMismatchError(f"Public key {pin} of {self.host} is not pinned")
+
+    connection_cls = type("PinnedConnection", (base.ConnectionCls,), {"connect": connect})
+    pool_cls = type("PinnedPool", (base,), {"ConnectionCls": connection_cls})
+    manager.pool_classes_by_scheme = {**manager.pool_classes_by_scheme, "https": pool_cls}
+
+
+class PinningAdapter(HTTPAdapter):
+    """Only talks to https servers whose public key matches one of the pins, even through proxies"""
+
+    def __init__(self, pins: list[str], **kwargs):
+        self.pins = set(pins)
+        super().__init__(**kwargs)
+
+    def init_poolmanager(self, *args, **kwargs):
+        super().init_poolmanager(*args, **kwargs)
+        pin_pool_manager(self.poolmanager, self.pins)
+
+    def proxy_manager_for(self, proxy: str, **proxy_kwargs) -> PoolManager:
+        if proxy not in self.proxy_manager:
+            pin_pool_manager(super().proxy_manager_for(proxy, **proxy_kwargs), self.pins)
+        return self.proxy_manager[proxy]
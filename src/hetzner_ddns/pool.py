@@ -0,0 +1,53 @@
+import sys
+import time
+
+import requests
+from requests.adapters import BaseAdapter, HTTPAdapter
+
+from hetzner_ddns.cassette import Cassette
+
+# shared by the api client and the detection, so that connections are reused within a run and across daemon cycles
+adapter = HTTPAdapter()
+idle_timeout = 90.0
+last_used = 0.0
+# all requests are recorded to or replayed from this cassette if one is set
+cassette: Cassette | None = None
+
+
+def setup(max_idle_per_host: int, pool_idle_timeout: float, http_cassette: Cassette | None = None):
+    global adapter, idle_timeout, cassette
+    adapter = HTTPAdapter(pool_maxsize=max_idle_per_host)
+    idle_timeout = pool_idle_timeout
+    cassette = http_cassette
+
+
+def touch(resp: requests.Response, *args, **kwargs):
+    global last_used
+    last_used = time.monotonic()
+
+
+def mount(session: requests.Session, adapters: dict[str, BaseAdapter] | None = None):
+    """Send requests of the session through the shared connection pools or the given adapters by url prefix
+
+    Connections which have been idle for longer than the idle timeout are closed instead of being reused, since servers
+    or NAT gateways have likely dropped them already.
+    """
+    if time.monotonic() - last_used > idle_timeout:
+        adapter.close()
+    for prefix, i in {"https://": adapter, "http://": adapter, **(adapters or {})}.items():
+        session.mount(prefix, cassette.adapter(i) if cassette is not None else i)
+    if touch not in session.hooks["response"]:
+        session.hooks["response"].append(touch)
+
+
+def setup_tls(backend: str):
+    """Verify certificates against the bundled `certifi` CAs (the default) or with the trust store of the system"""
+    if backend == "certifi":
+        return
+    if backend != "system":
+        sys.exit(f"Unknown tls_backend {backend}, expected certifi or system")
+    try:
+        import truststore
+    except ImportError:
+        sys.exit("The system tls_backend requires the truststore extra")
+    truststore.inject_into_ssl()
@@ -0,0 +1,23 @@
+import logging
+import os
+
+logger = logging.getLogger(__name__)
+
+
+def drop_privileges(cfg: dict):
+    """Switch to `run_as_user` and `run_as_group` if configured, e.g. when started as root by an init script"""
+    if "run_as_user" not in cfg and "run_as_group" not in cfg:
+        return
+    # not available on windows
+    import grp
+    import pwd
+
+    user = pwd.getpwnam(cfg["run_as_user"]) if "run_as_user" in cfg else pwd.getpwuid(os.getuid())
+    gid = grp.getgrnam(cfg["run_as_group"]).gr_gid if "run_as_group" in cfg else user.pw_gid
+    # the group has to be changed first because that is not allowed anymore afterwards
+    os.initgroups(user.pw_name, gid)
+    os.setgid(gid)
+    os.setuid(user.pw_uid)
+    # so that the default state directory is the one of the new user
+    os.environ["HOME"] = user.pw_dir
+    logger.debug("Running as user %s (uid %s) and gid %s", user.pw_name, user.pw_uid, gid)
@@ -0,0 +1,312 @@
+import logging
+import os
+import socket
+import time
+
+import requests
+from hetzner_dns_api import DnsApi, InvalidResponseError
+from urllib3.util import Timeout
+
+from hetzner_ddns import __version__, pool, resolve
+from hetzner_ddns.config import parse_duration
+from hetzner_ddns.hetzner_api import HetznerApi
+from hetzner_ddns.logs import log_http_response, register_secret
+from hetzner_ddns.state import StateStore
+
+logger = logging.getLogger(__name__)
+
+CLOUDFLARE_URL = "https://api.cloudflare.com/client/v4"
+DESEC_URL = "https://desec.io/api/v1"
+DUCKDNS_URL = "https://www.duckdns.org/update"
+
+
+class Provider:
+    """A DNS hosting service whose records are kept up to date
+
+    Records are dicts with at least the `id`, `name`, `type`, `value` and `ttl` of a record, plus whatever else the
+    provider needs to update them.
+    """
+
+    # ttl of updated records, short so that changed addresses propagate quickly
+    ttl: int | None = 60
+    # prepended to zone names in the cache of zone ids, so that providers hosting the same zone don't share ids
+    zone_cache_prefix = ""
+
+    def __init__(self, cfg: dict, store: StateStore):
+        self.cfg = cfg
+        self.store = store
+
+    def find_records(self, zone: str, name: str) -> list[dict]:
+        """All records called `name` (relative to the zone, `@` for its apex) in `zone`"""
+        raise NotImplementedError
+
+    def update_record(self, zone: str, record: dict, value: str, ttl: int | None):
+        """Set a new value of a record, which only needs to have the `id`, `name` and `type` of `find_records()`"""
+        raise NotImplementedError
+
+    def create_record(self, zone: str, name: str, typ: str, value: str, ttl: int | None) -> dict:
+        raise NotImplementedError
+
+    def lookup_zone_id(self, zone: str) -> str:
+        raise NotImplementedError
+
+    def get_zone_id(self, zone: str) -> str:
+        """Look up the id of a zone, which is cached in the state directory for `zone_cache_ttl`"""
+        cached = self.store.get_zone(self.zone_cache_prefix + zone)
+        if cached is not None and time.time() - cached["time"] < parse_duration(self.cfg.get("zone_cache_ttl", "1d")):
+            return cached["id"]
+        zone_id = self.lookup_zone_id(zone)
+        self.store.set_zone(self.zone_cache_prefix + zone, zone_id)
+        return zone_id
+
+    def forget_zone_id(self, zone: str):
+        # the zone might have been recreated with a different id
+        self.store.forget_zone(self.zone_cache_prefix + zone)
+
+
+class HetznerProvider(Provider):
+    def __init__(self, cfg: dict, store: StateStore, api: DnsApi | None = None):
+        """Create a provider talking to `api`, by default a client configured by `cfg`"""
+        super().__init__(cfg, store)
+        self.api: DnsApi = api if api is not None else create_api(cfg)
+
+    def lookup_zone_id(self, zone: str) -> str:
+        return self.api.get_zone_by_name(zone)["id"]
+
+    def find_records(self, zone: str, name: str) -> list[dict]:
+        zone_id = self.get_zone_id(zone)
+        try:
+            # copies, since the cached records are updated in place
+            return [dict(i) for i in self.api.get_records(zone_id) if i["name"] == name]
+        except Exception:
+            self.forget_zone_id(zone)
+            raise
+
+    def update_record(self, zone: str, record: dict, value: str, ttl: int | None):
+        data = {"name": record["name"], "ttl": ttl, "type": record["type"], "value": value}
+        self.api.update_record(record["id"], {**data, "zone_id": record.get("zone_id") or self.get_zone_id(zone)})
+
+    def create_record(self, zone: str, name: str, typ: str, value: str, ttl: int | None) -> dict:
+        data = {"name": name, "type": typ, "value": value, "zone_id": self.get_zone_id(zone)}
+        return self.api.create_record(data if ttl is None else {**data, "ttl": ttl})
+
+
+def create_api(cfg: dict) -> HetznerApi:
+    # the environment variable allows pointing e.g. a packaged service at a mock server without editing its config
+    url = os.environ.get("HETZNER_DDNS_API_URL") or cfg.get("api_url")
+    return HetznerApi(
+        cfg["api_token"],
+        cfg.get("update_rate"),
+        url,
+        cfg.get("api_retries", 0),
+        cfg.get("api_proxy"),
+        resolve.from_config(cfg),
+        api_timeout(cfg),
+    )
+
+
+def api_timeout(cfg: dict) -> Timeout:
+    return Timeout(
+        connect=parse_duration(cfg.get("api_connect_timeout", "10s")),
+        total=parse_duration(cfg.get("api_timeout", "30s")),
+    )
+
+
+class HttpProvider(Provider):
+    """Base of providers with a plain REST api, which is talked to through the shared connection pools"""
+
+    def __init__(self, cfg: dict, store: StateStore):
+        super().__init__(cfg, store)
+        self.sess = requests.Session()
+        self.sess.headers["User-Agent"] = f"hetzner_ddns/{__version__}"
+        self.sess.hooks["response"].append(log_http_response)
+        proxy = cfg.get("api_proxy")
+        self.proxies = {"http": proxy, "https": proxy} if proxy else {}
+        self.timeout = api_timeout(cfg)
+
+    def request(self, method: str, url: str, **kwargs) -> requests.Response:
+        pool.mount(self.sess)
+        return self.sess.request(method, url, proxies=self.proxies, timeout=self.timeout, **kwargs)
+
+
+def fqdn(zone: str, name: str) -> str:
+    return zone if name == "@" else f"{name}.{zone}"
+
+
+class CloudflareProvider(HttpProvider):
+    """Cloudflare, authenticated with an api token which is allowed to edit the DNS of the zones"""
+
+    zone_cache_prefix = "cloudflare:"
+
+    def __init__(self, cfg: dict, store: StateStore):
+        super().__init__(cfg, store)
+        self.sess.headers["Authorization"] = f"Bearer {cfg['cloudflare_api_token']}"
+        register_secret(cfg["cloudflare_api_token"])
+
+    def call(self, method: str, path: str, **kwargs) -> dict | list:
+        resp = self.request(method, CLOUDFLARE_URL + path, **kwargs)
+        if not resp.ok:
+            try:
+                errors = "; ".join(f"{i['message']} ({i['code']})" for i in resp.json()["errors"])
+            except (ValueError, KeyError, TypeError):
+                errors = resp.text[:200]
+            raise requests.HTTPError(f"{resp.status_code} {resp.reason} for {path}: {errors}", response=resp)
+        return resp.json()["result"]
+
+    def lookup_zone_id(self, zone: str) -> str:
+        zones = self.call("GET", "/zones", params={"name": zone})
+        if not zones:
+            raise LookupError(f"Zone {zone} not found in Cloudflare")
+        return zones[0]["id"]
+
+    def find_records(self, zone: str, name: str) -> list[dict]:
+        zone_id = self.get_zone_id(zone)
+        try:
+            params = {"name": fqdn(zone, name), "per_page": 100}
+            records = self.call("GET", f"/zones/{zone_id}/dns_records", params=params)
+        except Exception:
+            self.forget_zone_id(zone)
+            raise
+        return [self.parse_record(i, zone_id, name) for i in records]
+
+    def update_record(self, zone: str, record: dict, value: str, ttl: int | None):
+        zone_id = record.get("zone_id") or self.get_zone_id(zone)
+        data = {"content": value} if ttl is None else {"content": value, "ttl": ttl}
+        self.call("PATCH", f"/zones/{zone_id}/dns_records/{record['id']}", json=data)
+
+    def create_record(self, zone: str, name: str, typ: str, value: str, ttl: int | None) -> dict:
+        zone_id = self.get_zone_id(zone)
+        # cloudflare's ttl of 1 means automatic
+        data = {"name": fqdn(zone, name), "type": typ, "content": value, "ttl": ttl or 1}
+        return self.parse_record(self.call("POST", f"/zones/{zone_id}/dns_records", json=data), zone_id, name)
+
+    @staticmethod
+    def parse_record(data: dict, zone_id: str, name: str) -> dict:
+        return {
+            "id": data["id"],
+            "zone_id": zone_id,
+            "name": name,
+            "type": data["type"],
+            "value": data["content"],
+            "ttl": data["ttl"],
+        }
+
+
+class DesecProvider(HttpProvider):
+    """deSEC, whose api manages RRsets (all records of a name and type) instead of single records
+
+    An RRset with several addresses is replaced by the single detected address.
+    """
+
+    # the lowest ttl deSEC accepts by default
+    ttl = 3600
+
+    def __init__(self, cfg: dict, store: StateStore):
+        super().__init__(cfg, store)
+        self.sess.headers["Authorization"] = f"Token {cfg['desec_token']}"
+        register_secret(cfg["desec_token"])
+
+    def call(self, method: str, path: str, **kwargs) -> dict | list:
+        resp = self.request(method, DESEC_URL + path, **kwargs)
+        if not resp.ok:
+            raise requests.HTTPError(f"{resp.status_code} {resp.reason} for {path}: {resp.text[:200]}", response=resp)
+        return resp.json() if resp.content else {}
+
+    def find_records(self, zone: str, name: str) -> list[dict]:
+        rrsets = self.call("GET", f"/domains/{zone}/rrsets/", params={"subname": "" if name == "@" else name})
+        return [self.parse_rrset(i, name) for i in rrsets if i["records"]]
+
+    def update_record(self, zone: str, record: dict, value: str, ttl: int | None):
+        data = {"records": [value]} if ttl is None else {"records": [value], "ttl": ttl}
+        # the apex is addressed as @ in urls
+        self.call("PATCH", f"/domains/{zone}/rrsets/{record['name']}/{record['type']}/", json=data)
+
+    def create_record(self, zone: str, name: str, typ: str, value: str, ttl: int | None) -> dict:
+        data = {"subname": "" if name == "@" else name, "type": typ, "records": [value], "ttl": ttl or self.ttl}
+        return self.parse_rrset(self.call("POST", f"/domains/{zone}/rrsets/", json=data), name)
+
+    @staticmethod
+    def parse_rrset(data: dict, name: str) -> dict:
+        return {
+            "id": f"{data['name']}/{data['type']}",
+            "name": name,
+            "type": data["type"],
+            "value": data["records"][0],
+            "ttl": data["ttl"],
+        }
+
+
+class DuckDnsProvider(HttpProvider):
+    """DuckDNS, whose api can only set the addresses of a subdomain of duckdns.org
+
+    Since records can't be listed, their current values are resolved via DNS.
+    Subdomains only get an AAAA record if they already resolve to an IPv6 address or `duckdns_ipv6` is enabled, so
+    that IPv4-only hosts don't fail to detect an IPv6 address.
+    """
+
+    # duckdns doesn't allow changing the ttl
+    ttl = None
+
+    def __init__(self, cfg: dict, store: StateStore):
+        super().__init__(cfg, store)
+        register_secret(cfg["duckdns_token"])
+
+    def find_records(self, zone: str, name: str) -> list[dict]:
+        records = []
+        for typ, family in (("A", socket.AF_INET), ("AAAA", socket.AF_INET6)):
+            try:
+                value = socket.getaddrinfo(fqdn(zone, name), None, family)[0][4][0]
+            except socket.gaierror:
+                value = ""
+            if value or typ == "A" or self.cfg.get("duckdns_ipv6", False):
+                records.append(
+                    {"id": f"{fqdn(zone, name)}/{typ}", "name": name, "type": typ, "value": value, "ttl": None}
+                )
+        return records
+
+    def update_record(self, zone: str, record: dict, value: str, ttl: int | None):
+        if not value:
+            # an empty address would make duckdns use the one the request came from, e.g. when rolling back
+            logger.warning("DuckDNS can't remove the %s record of %s, keeping it", record["type"], record["name"])
+            return
+        params = {"domains": record["name"], "token": self.cfg["duckdns_token"]}
+        params["ip" if record["type"] == "A" else "ipv6"] = value
+        try:
+            resp = self.request("GET", DUCKDNS_URL, params=params)
+        except requests.RequestException as e:
+            # the messages of requests contain the url and with it the token
+            reason = getattr(e.args[0] if e.args else None, "reason", None) or type(e).__name__
+            raise type(e)(f"Could not reach DuckDNS to update {record['name']}: {reason}") from None
+        if not resp.ok:
+            raise requests.HTTPError(f"{resp.status_code} {resp.reason} from DuckDNS", response=resp)
+        if resp.text.strip() != "OK":
+            # duckdns answers KO to unknown tokens and subdomains
+            raise InvalidResponseError(f"DuckDNS did not update {record['name']}: {resp.text[:200].strip()}")
+
+
+PROVIDERS = {
+    "hetzner": HetznerProvider,
+    "cloudflare": CloudflareProvider,
+    "desec": DesecProvider,
+    "duckdns": DuckDnsProvider,
+}
+
+
+class Providers:
+    """The providers of targets by their `provider` (default `hetzner`), created when first needed"""
+
+    def __init__(self, cfg: dict, store: StateStore):
+        self.cfg = cfg
+        self.store = store
+        self.instances: dict[str, Provider] = {}
+
+    def get(self, name: str | None) -> Provider:
+        name = name or "hetzner"
+        if name not in self.instances:
+            if name not in PROVIDERS:
+                raise ValueError(f"Unknown provider {name}, expected one of {', '.join(PROVIDERS)}")
+            self.instances[name] = PROVIDERS[name](self.cfg, self.store)
+        return self.instances[name]
+
+    def for_target(self, target: dict) -> Provider:
+        return self.get(target.get("provider"))
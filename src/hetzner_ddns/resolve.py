@@ -0,0 +1,96 @@
+import ipaddress
+import random
+import socket
+import struct
+from collections.abc import Callable
+from urllib.parse import urlsplit
+
+import requests
+from requests.adapters import HTTPAdapter
+
+
+def static(address: str) -> Callable[[str], str]:
+    return lambda hostname: address
+
+
+def doh(url: str) -> Callable[[str], str]:
+    """Resolve IPv4 addresses via the json api of a DNS-over-HTTPS resolver like `https://1.1.1.1/dns-query`"""
+
+    def resolve(hostname: str) -> str:
+        resp = requests.get(
+            url, params={"name": hostname, "type": "A"}, headers={"Accept": "application/dns-json"}, timeout=10
+        )
+        resp.raise_for_status()
+        for answer in resp.json().get("Answer", []):
+            if answer["type"] == 1:
+                return answer["data"]
+        raise OSError(f"{url} returned no address for {hostname}")
+
+    return resolve
+
+
+def do53(server: str) -> Callable[[str], str]:
+    """Resolve IPv4 addresses by querying the given DNS server directly"""
+
+    def resolve(hostname: str) -> str:
+        query_id = random.randrange(1 << 16)
+        question = b"".join(bytes([len(i)]) + i.encode() for i in hostname.rstrip(".").split(".")) + b"\0"
+        query = struct.pack("!HHHHHH", query_id, 0x0100, 1, 0, 0, 0) + question + struct.pack("!HH", 1, 1)
+        with socket.socket(socket.AF_INET6 if ":" in server else socket.AF_INET, socket.SOCK_DGRAM) as sock:
+            sock.settimeout(5)
+            sock.sendto(query, (server, 53))
+            resp = sock.recv(4096)
+        resp_id, flags, _, answer_count, _, _ = struct.unpack("!HHHHHH", resp[:12])
+        if resp_id != query_id or flags & 0xF:
+            raise OSError(f"{server} failed to resolve {hostname} (rcode {flags & 0xF})")
+        offset = 12 + len(question) + 4
+        for _ in range(answer_count):
+            # names in answers are usually compressed to a 2 byte pointer but may be spelled out
+            while resp[offset] not in (0, *range(0xC0, 0x100)):
+                offset += resp[offset] + 1
+            offset += 2 if resp[offset] >= 0xC0 else 1
+            typ, _, _, length = struct.unpack("!HHIH", resp[offset : offset + 10])
+            offset += 10
+            if typ == 1 and length == 4:
+                return str(ipaddress.IPv4Address(resp[offset : offset + 4]))
+            offset += length
+        raise OSError(f"{server} returned no address for {hostname}")
+
+    return resolve
+
+
+def from_config(cfg: dict) -> Callable[[str], str] | None:
+    """The configured way to resolve the hostname of the api, or None to use the system resolver"""
+    if "api_address" in cfg:
+        return static(cfg["api_address"])
+    if "api_resolver" in cfg:
+        resolver = cfg["api_resolver"]
+        return doh(resolver) if resolver.startswith("https://") else do53(resolver)
+    return None
+
+
+class ResolvingAdapter(HTTPAdapter):
+    """Connects to the addresses returned by `resolve` while still verifying certificates against the hostname"""
+
+    def __init__(self, resolve: Callable[[str], str], **kwargs):
+        self.resolve = resolve
+        self.addresses: dict[str, str] = {}
+        super().__init__(**kwargs)
+
+    def send(self, request: requests.PreparedRequest, **kwargs) -> requests.Response:
+        url = urlsplit(request.url)
+        if url.hostname not in self.addresses:
+            self.addresses[url.hostname] = self.resolve(url.hostname)
+        address = self.addresses[url.hostname]
+        host = f"[{address}]" if ":" in address else address
+        request.headers["Host"] = url.netloc
+        request.url = url._replace(netloc=f"{host}:{url.port}" if url.port else host).geturl()
+        request.hostname = url.hostname
+        return super().send(request, **kwargs)
+
+    def build_connection_pool_key_attributes(self, request: requests.PreparedRequest, verify, cert=None):
+        host_params, pool_kwargs = super().build_connection_pool_key_attributes(request, verify, cert)
+        if host_params["scheme"] == "https":
+            pool_kwargs["server_hostname"] = request.hostname
+            pool_kwargs["assert_hostname"] = request.hostname
+        return host_params, pool_kwargs
@@ -0,0 +1,185 @@
+import ctypes
+import errno
+import logging
+import os
+import platform
+import site
+import struct
+import sys
+import sysconfig
+from pathlib import Path
+
+import hetzner_dns_api
+
+logger = logging.getLogger(__name__)
+
+SYS_LANDLOCK_CREATE_RULESET = 444
+SYS_LANDLOCK_ADD_RULE = 445
+SYS_LANDLOCK_RESTRICT_SELF = 446
+LANDLOCK_CREATE_RULESET_VERSION = 1
+LANDLOCK_RULE_PATH_BENEATH = 1
+PR_SET_NO_NEW_PRIVS = 38
+PR_SET_SECCOMP = 22
+SECCOMP_MODE_FILTER = 2
+
+# LANDLOCK_ACCESS_FS_* of the first landlock abi
+ACCESS_EXECUTE = 1 << 0
+ACCESS_WRITE_FILE = 1 << 1
+ACCESS_READ_FILE = 1 << 2
+ACCESS_READ_DIR = 1 << 3
+ACCESS_ALL_V1 = (1 << 13) - 1
+# LANDLOCK_ACCESS_FS_TRUNCATE, which exists since abi 3
+ACCESS_TRUNCATE = 1 << 14
+ACCESS_READ = ACCESS_READ_FILE | ACCESS_READ_DIR
+
+# locations of python, shared libraries and the programs run by hooks
+SYSTEM_PATHS = ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/opt", sys.prefix, sys.base_prefix]
+# the stdlib, site-packages and our own packages, but not whatever else is on sys.path like the working directory
+PYTHON_PATHS = [
+    *(sysconfig.get_path(name) for name in ("stdlib", "platstdlib", "purelib", "platlib")),
+    *site.getsitepackages(),
+    site.getusersitepackages(),
+    str(Path(__file__).parent),
+    str(Path(hetzner_dns_api.__file__).parent),
+]
+# what name resolution, tls and the like need from /etc
+CONFIG_PATHS = [
+    "/etc/ssl",
+    "/etc/ca-certificates",
+    "/etc/pki",
+    "/etc/resolv.conf",
+    "/etc/hosts",
+    "/etc/nsswitch.conf",
+    "/etc/gai.conf",
+    "/etc/host.conf",
+    "/etc/localtime",
+    "/etc/passwd",
+    "/etc/group",
+    "/etc/ld.so.cache",
+    "/proc",
+    "/run/systemd/resolve",
+]
+
+# syscalls which are never needed but useful after a compromise, by machine
+DENIED_SYSCALLS = {
+    # ptrace, mount, umount2, pivot_root, chroot, swapon, swapoff, reboot, init_module, delete_module, kexec_load,
+    # add_key, request_key, keyctl, unshare, perf_event_open, open_by_handle_at, setns, process_vm_readv,
+    # process_vm_writev, finit_module, kexec_file_load, bpf, userfaultfd
+    "x86_64": (
+        0xC000003E,
+        [101, 165, 166, 155, 161, 167, 168, 169, 175, 176, 246, 248, 249, 250, 272, 298, 304, 308, 310, 311, 313]
+        + [320, 321, 323],
+    ),
+    "aarch64": (
+        0xC00000B7,
+        [117, 40, 39, 41, 51, 224, 225, 142, 105, 106, 104, 217, 218, 219, 97, 241, 265, 268, 270, 271, 273, 294]
+        + [280, 282],
+    ),
+}
+SECCOMP_RET_KILL_PROCESS = 0x80000000
+SECCOMP_RET_ALLOW = 0x7FFF0000
+SECCOMP_RET_ERRNO = 0x00050000
+# syscalls of the x32 abi have this bit set and are denied as a whole
+X32_SYSCALL_BIT = 0x40000000
+
+# loaded by apply_sandbox, since loading the process' own symbols this way fails on windows
+libc: ctypes.CDLL | None = None
+
+
+def check(result: int) -> int:
+    if result < 0:
+        err = ctypes.get_errno()
+        raise OSError(err, os.strerror(err))
+    return result
+
+
+def apply_landlock(read_paths: list[str], write_paths: list[str]):
+    abi = check(libc.syscall(SYS_LANDLOCK_CREATE_RULESET, None, 0, LANDLOCK_CREATE_RULESET_VERSION))
+    handled = ACCESS_ALL_V1 | (ACCESS_TRUNCATE if abi >= 3 else 0)
+    ruleset_attr = struct.pack("Q", handled)
+    ruleset_fd = check(libc.syscall(SYS_LANDLOCK_CREATE_RULESET, ruleset_attr, len(ruleset_attr), 0))
+    try:
+        rules = [(i, ACCESS_READ | ACCESS_EXECUTE) for i in read_paths] + [(i, handled) for i in write_paths]
+        for path, access in rules:
+            try:
+                fd = os.open(path, os.O_PATH | os.O_CLOEXEC)
+            except OSError:
+                continue
+            try:
+                if not os.path.isdir(path):
+                    # directory specific rights can't be granted on files
+                    access &= ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE
+                path_beneath = struct.pack("=Qi", access & handled, fd)
+                check(libc.syscall(SYS_LANDLOCK_ADD_RULE, ruleset_fd, LANDLOCK_RULE_PATH_BENEATH, path_beneath, 0))
+            finally:
+                os.close(fd)
+        check(libc.syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset_fd, 0))
+    finally:
+        os.close(ruleset_fd)
+
+
+def seccomp_program(arch: int, syscalls: list[int], deny_x32: bool) -> bytes:
+    """A classic bpf program which makes the given syscalls fail with EPERM"""
+    # (code, jump if true, jump if false, k) with the jump targets as absolute indices for now
+    deny = "deny"
+    program = [
+        (0x20, 0, 0, 4),  # load the architecture
+        (0x15, 3, 2, arch),  # and kill the process if it is an unexpected one, which could bypass the filter
+        (0x06, 0, 0, SECCOMP_RET_KILL_PROCESS),
+        (0x20, 0, 0, 0),  # load the syscall number
+    ]
+    if deny_x32:
+        program.append((0x35, deny, len(program) + 1, X32_SYSCALL_BIT))
+    for nr in syscalls:
+        program.append((0x15, deny, len(program) + 1, nr))
+    program.append((0x06, 0, 0, SECCOMP_RET_ALLOW))
+    program.append((0x06, 0, 0, SECCOMP_RET_ERRNO | errno.EPERM))
+    deny_index = len(program) - 1
+    instructions = []
+    for i, (code, jt, jf, k) in enumerate(program):
+        if code & 0x07 == 0x05:
+            # jumps are relative to the next instruction
+            jt = (deny_index if jt == deny else jt) - i - 1
+            jf = jf - i - 1
+        instructions.append(struct.pack("HBBI", code, jt, jf, k))
+    return b"".join(instructions)
+
+
+def apply_seccomp():
+    machine = platform.machine()
+    if machine not in DENIED_SYSCALLS:
+        logger.warning("No seccomp filter is available for %s", machine)
+        return
+    arch, syscalls = DENIED_SYSCALLS[machine]
+    program = seccomp_program(arch, syscalls, machine == "x86_64")
+    filters = ctypes.create_string_buffer(program)
+    # struct sock_fprog
+    fprog = struct.pack("HxxxxxxP", len(program) // 8, ctypes.addressof(filters))
+    check(libc.prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, fprog, 0, 0))
+
+
+def apply_sandbox(cfg: dict, config_path: Path, state_dir: Path):
+    """Restrict file system access and dangerous syscalls of this process if `sandbox = true` is configured"""
+    global libc
+    if not cfg.get("sandbox", False):
+        return
+    if not sys.platform.startswith("linux"):
+        logger.warning("Sandboxing is only supported on linux")
+        return
+    state_dir.mkdir(parents=True, exist_ok=True)
+    read_paths = [*SYSTEM_PATHS, *PYTHON_PATHS, *CONFIG_PATHS, str(config_path), *cfg.get("sandbox_read_paths", [])]
+    write_paths = [str(state_dir), "/dev/null", "/tmp", *cfg.get("sandbox_write_paths", [])]
+    # files which are replaced atomically need access to their directory
+    for key in ("log_file", "metrics_textfile", "output_file"):
+        if key in cfg:
+            write_paths.append(str(Path(cfg[key]).parent))
+    if "git_snapshot_dir" in cfg:
+        write_paths.append(cfg["git_snapshot_dir"])
+
+    libc = ctypes.CDLL(None, use_errno=True)
+    check(libc.prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))
+    try:
+        apply_landlock(read_paths, write_paths)
+    except OSError as e:
+        logger.warning("Could not apply landlock rules, the kernel might not support them: %s", e)
+    apply_seccomp()
@@ -0,0 +1,60 @@
+from datetime import datetime, timedelta
+
+# (minimum, maximum) of the fields minute, hour, day of month, month and day of week
+FIELD_RANGES = ((0, 59), (0, 23), (1, 31), (1, 12), (0, 7))
+
+
+def parse_field(field: str, minimum: int, maximum: int) -> set[int]:
+    values = set()
+    for part in field.split(","):
+        expr, _, step = part.partition("/")
+        if expr == "*":
+            start, end = minimum, maximum
+        elif "-" in expr:
+            start, end = (int(i) for i in expr.split("-", 1))
+        else:
+            start = int(expr)
+            end = maximum if step else start
+        if not minimum <= start <= end <= maximum:
+            raise ValueError(f"{part} is out of range {minimum}-{maximum}")
+        values.update(range(start, end + 1, int(step) if step else 1))
+    return values
+
+
+class CronSchedule:
+    """A cron expression with the five fields minute, hour, day of month, month and day of week"""
+
+    def __init__(self, expression: str):
+        fields = expression.split()
+        if len(fields) != 5:
+            raise ValueError(f"Cron expression {expression!r} does not consist of five fields")
+        self.minutes, self.hours, self.days, self.months, self.weekdays = (
+            parse_field(field, *limits) for field, limits in zip(fields, FIELD_RANGES)
+        )
+        # 0 and 7 are both sunday
+        if 7 in self.weekdays:
+            self.weekdays.add(0)
+        # like in cron, a day matches either field if both day of month and day of week are restricted
+        self.any_day = fields[2] != "*" and fields[4] != "*"
+        self.expression = expression
+
+    def matches_day(self, time: datetime) -> bool:
+        day = time.day in self.days
+        weekday = (time.weekday() + 1) % 7 in self.weekdays
+        return time.month in self.months and (day or weekday if self.any_day else day and weekday)
+
+    def next_after(self, time: datetime) -> datetime:
+        """The first matching minute after `time`"""
+        time = time.replace(second=0, microsecond=0) + timedelta(minutes=1)
+        # every possible combination of days occurs within a few years
+        limit = time + timedelta(days=5 * 366)
+        while time < limit:
+            if not self.matches_day(time):
+                time = time.replace(hour=0, minute=0) + timedelta(days=1)
+            elif time.hour not in self.hours:
+                time = time.replace(minute=0) + timedelta(hours=1)
+            elif time.minute not in self.minutes:
+                time += timedelta(minutes=1)
+            else:
+                return time
+        raise ValueError(f"Cron expression {self.expression!r} never matches")
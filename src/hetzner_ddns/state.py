@@ -0,0 +1,137 @@
+import json
+import os
+import time
+from contextlib import contextmanager
+from datetime import datetime, timezone
+from pathlib import Path
+
+from hetzner_dns_api import RecordType
+
+try:
+    import fcntl
+except ImportError:
+    # windows
+    import msvcrt
+
+    fcntl = None
+
+
+def default_state_dir() -> Path:
+    base = os.environ.get("XDG_STATE_HOME") or Path.home() / ".local" / "state"
+    return Path(base) / "hetzner_ddns"
+
+
+class LockedError(Exception):
+    """The state directory is locked by another instance"""
+
+
+class StateStore:
+    """Small json-file based store for everything that needs to be remembered between runs"""
+
+    def __init__(self, path: Path):
+        self.path = path
+
+    def load(self, name: str, default):
+        try:
+            with open(self.path / name) as f:
+                return json.load(f)
+        except FileNotFoundError:
+            return default
+
+    def save(self, name: str, data):
+        self.path.mkdir(parents=True, exist_ok=True)
+        tmp_file = self.path / f"{name}.tmp"
+        with open(tmp_file, "w") as f:
+            json.dump(data, f, indent=2)
+        tmp_file.replace(self.path / name)
+
+    @contextmanager
+    def lock(self, timeout: float = 0):
+        """Hold an exclusive lock on the state directory, waiting up to `timeout` seconds for other instances
+
+        Raises `LockedError` if the lock could not be acquired.
+        """
+        self.path.mkdir(parents=True, exist_ok=True)
+        with open(self.path / "lock", "w") as f:
+            deadline = time.monotonic() + timeout
+            while True:
+                try:
+                    if fcntl is not None:
+                        fcntl.flock(f, fcntl.LOCK_EX | fcntl.LOCK_NB)
+                    else:
+                        msvcrt.locking(f.fileno(), msvcrt.LK_NBLCK, 1)
+                    break
+                except (BlockingIOError, PermissionError):
+                    if time.monotonic() >= deadline:
+                        raise LockedError(f"{self.path / 'lock'} is locked")
+                    time.sleep(0.5)
+            yield
+
+    def get_history(self) -> list[dict]:
+        return self.load("history.json", [])
+
+    def add_run(self, updates: list[dict], rollback: bool = False) -> dict:
+        history = self.get_history()
+        run = {
+            "id": history[-1]["id"] + 1 if history else 1,
+            "time": datetime.now(timezone.utc).isoformat(timespec="seconds"),
+            "updates": updates,
+        }
+        if rollback:
+            run["rollback"] = True
+        history.append(run)
+        self.save("history.json", history)
+        return run
+
+    def get_zone(self, name: str) -> dict | None:
+        """The cached id of a zone and the time at which it was looked up"""
+        return self.load("zones.json", {}).get(name)
+
+    def set_zone(self, name: str, zone_id: str):
+        zones = self.load("zones.json", {})
+        zones[name] = {"id": zone_id, "time": time.time()}
+        self.save("zones.json", zones)
+
+    def forget_zone(self, name: str):
+        zones = self.load("zones.json", {})
+        if zones.pop(name, None) is not None:
+            self.save("zones.json", zones)
+
+    def get_last_write(self, record_id: str) -> float:
+        """Time at which the record was last written to the Hetzner API (or 0 if it never was)"""
+        return self.load("last_writes.json", {}).get(record_id, 0)
+
+    def set_last_write(self, record_id: str):
+        last_writes = self.load("last_writes.json", {})
+        last_writes[record_id] = time.time()
+        self.save("last_writes.json", last_writes)
+
+    def get_published(self) -> dict[str, dict[str, str]]:
+        """The values last written to each record (`<record>.<zone>`) by record type"""
+        return self.load("published.json", {})
+
+    def get_published_addresses(self) -> dict[str, str]:
+        """The addresses last written to any record by record type"""
+        return {typ: value for types in self.get_published().values() for typ, value in types.items()}
+
+    def set_published(self, updates: list[dict]):
+        published = self.get_published()
+        for i in updates:
+            published.setdefault(f"{i['record']}.{i['zone']}", {})[i["type"]] = i["new"]
+        self.save("published.json", published)
+
+    def get_managed_types(self, record: str) -> list[str] | None:
+        """Types of the records of a target (`<record>.<zone>`) found by its last complete lookup"""
+        return self.load("managed_types.json", {}).get(record)
+
+    def set_managed_types(self, record: str, types: list[str]):
+        managed_types = self.load("managed_types.json", {})
+        managed_types[record] = types
+        self.save("managed_types.json", managed_types)
+
+    def set_last_success(self):
+        """Remember the time and the published addresses of the latest successful run"""
+        data = {"time": datetime.now(timezone.utc).isoformat(timespec="seconds")}
+        for typ, address in self.get_published_addresses().items():
+            data["ipv4" if typ == RecordType.A else "ipv6"] = address
+        self.save("last_success.json", data)
@@ -0,0 +1,29 @@
+import logging
+import os
+import socket
+
+logger = logging.getLogger(__name__)
+
+
+def sd_notify(**fields):
+    """Send a state change like `READY=1` to the service manager if running as a systemd `Type=notify` service"""
+    path = os.environ.get("NOTIFY_SOCKET")
+    if not path:
+        return
+    if path.startswith("@"):
+        # abstract namespace socket
+        path = "\0" + path[1:]
+    message = "\n".join(f"{k}={v}" for k, v in fields.items())
+    try:
+        with socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM) as sock:
+            sock.sendto(message.encode(), path)
+    except OSError as e:
+        logger.warning("Could not notify systemd: %s", e)
+
+
+def watchdog_interval() -> float | None:
+    """Seconds after which `WATCHDOG=1` needs to be sent again if systemd watches this process via `WatchdogSec=`"""
+    if "WATCHDOG_USEC" not in os.environ or os.environ.get("WATCHDOG_PID", str(os.getpid())) != str(os.getpid()):
+        return None
+    # ping twice as often as required so that a slightly late ping doesn't kill the service
+    return int(os.environ["WATCHDOG_USEC"]) / 1_000_000 / 2
@@ -0,0 +1,115 @@
+import sys
+from contextlib import nullcontext
+
+import requests
+
+from hetzner_ddns import metrics
+
+tracer = None
+tracer_provider = None
+meter_provider = None
+sentry_sdk = None
+
+
+def setup(cfg: dict):
+    """Export traces and metrics via OTLP if an endpoint is configured"""
+    global tracer, tracer_provider, meter_provider
+    if "otlp_endpoint" not in cfg:
+        return
+
+    try:
+        from opentelemetry import trace
+        from opentelemetry.exporter.otlp.proto.http.metric_exporter import OTLPMetricExporter
+        from opentelemetry.exporter.otlp.proto.http.trace_exporter import OTLPSpanExporter
+        from opentelemetry.metrics import CallbackOptions, Observation
+        from opentelemetry.sdk.metrics import MeterProvider
+        from opentelemetry.sdk.metrics.export import PeriodicExportingMetricReader
+        from opentelemetry.sdk.resources import Resource
+        from opentelemetry.sdk.trace import TracerProvider
+        from opentelemetry.sdk.trace.export import BatchSpanProcessor
+    except ImportError:
+        sys.exit("OpenTelemetry export requires the otel extra to be installed")
+
+    endpoint = cfg["otlp_endpoint"].rstrip("/")
+    headers = cfg.get("otlp_headers", {})
+    resource = Resource.create({"service.name": "hetzner_ddns"})
+
+    tracer_provider = TracerProvider(resource=resource)
+    tracer_provider.add_span_processor(
+        BatchSpanProcessor(OTLPSpanExporter(endpoint=f"{endpoint}/v1/traces", headers=headers))
+    )
+    trace.set_tracer_provider(tracer_provider)
+    tracer = trace.get_tracer("hetzner_ddns")
+
+    reader = PeriodicExportingMetricReader(OTLPMetricExporter(endpoint=f"{endpoint}/v1/metrics", headers=headers))
+    meter_provider = MeterProvider(resource=resource, metric_readers=[reader])
+    meter = meter_provider.get_meter("hetzner_ddns")
+
+    def observe(name: str):
+        def callback(options: CallbackOptions):
+            return [
+                Observation(value, dict(labels)) for (i_name, labels), value in metrics.values.items() if i_name == name
+            ]
+
+        return callback
+
+    otel_histograms = {}
+    for name, (typ, help_text) in metrics.METRICS.items():
+        if typ == "counter":
+            meter.create_observable_counter(name, callbacks=[observe(name)], description=help_text)
+        elif typ == "histogram":
+            otel_histograms[name] = meter.create_histogram(name, unit="s", description=help_text)
+        else:
+            meter.create_observable_gauge(name, callbacks=[observe(name)], description=help_text)
+    metrics.observers.append(lambda name, value, labels: otel_histograms[name].record(value, labels))
+
+
+def setup_sentry(cfg: dict):
+    """Report errors to sentry if a dsn is configured"""
+    global sentry_sdk
+    if "sentry_dsn" not in cfg:
+        return
+
+    try:
+        import sentry_sdk
+    except ImportError:
+        sys.exit("Sentry error reporting requires the sentry extra to be installed")
+    sentry_sdk.init(dsn=cfg["sentry_dsn"], environment=cfg.get("sentry_environment"))
+
+
+def report_error(e: Exception, **context):
+    """Send an error to sentry, tagged with the given context and the http status of failed api calls"""
+    if sentry_sdk is None:
+        return
+    with sentry_sdk.new_scope() as scope:
+        for k, v in context.items():
+            if v is not None:
+                scope.set_tag(k, v)
+        if isinstance(e, requests.HTTPError) and e.response is not None:
+            scope.set_tag("http_status", e.response.status_code)
+        sentry_sdk.capture_exception(e)
+
+
+def span(name: str, **attributes):
+    """Context manager tracing the enclosed code as a span (or doing nothing when tracing is disabled)"""
+    if tracer is None:
+        return nullcontext()
+    return tracer.start_as_current_span(name, attributes=attributes)
+
+
+def set_attribute(key: str, value):
+    """Set an attribute on the currently active span"""
+    if tracer is None:
+        return
+    from opentelemetry import trace
+
+    trace.get_current_span().set_attribute(key, value)
+
+
+def flush():
+    if tracer_provider is not None:
+        tracer_provider.force_flush()
+    if meter_provider is not None:
+        meter_provider.force_flush()
+    if sentry_sdk is not None:
+        sentry_sdk.flush()
@@ -0,0 +1,45 @@
+import logging
+import threading
+from collections.abc import Callable
+
+logger = logging.getLogger(__name__)
+
+SERVICE_NAME = "hetzner_ddns"
+
+
+def run_service(run: Callable[[threading.Event, threading.Event], None]):
+    """Connect to the windows service control manager and call `run(wakeup, stop)` as the service's main loop
+
+    This requires the `windows` extra.
+    """
+    import servicemanager
+    import win32service
+    import win32serviceutil
+
+    class Service(win32serviceutil.ServiceFramework):
+        _svc_name_ = SERVICE_NAME
+        _svc_display_name_ = "Hetzner DynDNS client"
+        _svc_description_ = "Keeps DNS records on Hetzner DNS pointed at the public address of this host"
+
+        def __init__(self, args):
+            super().__init__(args)
+            self.wakeup = threading.Event()
+            self.stop = threading.Event()
+
+        def SvcStop(self):
+            self.ReportServiceStatus(win32service.SERVICE_STOP_PENDING)
+            self.stop.set()
+            self.wakeup.set()
+
+        def SvcDoRun(self):
+            servicemanager.LogInfoMsg(f"{SERVICE_NAME} started")
+            try:
+                run(self.wakeup, self.stop)
+            except Exception:
+                logger.exception("Service failed")
+                raise
+            servicemanager.LogInfoMsg(f"{SERVICE_NAME} stopped")
+
+    servicemanager.Initialize()
+    servicemanager.PrepareToHostSingle(Service)
+    servicemanager.StartServiceCtrlDispatcher()
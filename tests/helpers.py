@@ -0,0 +1,70 @@
+import threading
+import uuid
+
+import requests
+from requests.adapters import BaseAdapter
+from requests.structures import CaseInsensitiveDict
+
+from hetzner_ddns.mock_server import API_PREFIX, MockApi, MockApiServer
+
+
+class MockApiTestCase:
+    """Mixin serving a fresh `MockApi` for every test at `self.url`"""
+
+    def setUp(self):
+        super().setUp()
+        self.api = MockApi()
+        self.server = MockApiServer(("127.0.0.1", 0), self.api)
+        threading.Thread(target=self.server.serve_forever, daemon=True).start()
+        self.addCleanup(self.server.server_close)
+        self.addCleanup(self.server.shutdown)
+        self.url = f"http://127.0.0.1:{self.server.server_address[1]}{API_PREFIX}"
+
+
+class StaticAdapter(BaseAdapter):
+    """Answers requests with the given `(status, headers, body)` responses in order, remembering the requests"""
+
+    def __init__(self, *responses: tuple[int, dict, str]):
+        super().__init__()
+        self.responses = list(responses)
+        self.requests: list[requests.PreparedRequest] = []
+
+    def send(self, request: requests.PreparedRequest, **kwargs) -> requests.Response:
+        self.requests.append(request)
+        status, headers, body = self.responses.pop(0)
+        resp = requests.Response()
+        resp.request = request
+        resp.url = request.url
+        resp.status_code = status
+        resp.headers = CaseInsensitiveDict(headers)
+        resp.encoding = "utf-8"
+        resp._content = body.encode()
+        return resp
+
+    def close(self):
+        pass
+
+
+class FakeApi:
+    """In-memory `DnsApi` with the records of one zone, which are kept by id"""
+
+    def __init__(self, zone_name: str):
+        self.zone = {"id": "zone", "name": zone_name}
+        self.records: dict[str, dict] = {}
+
+    def get_zone_by_name(self, zone_name: str) -> dict:
+        if zone_name != self.zone["name"]:
+            raise KeyError(zone_name)
+        return self.zone
+
+    def get_records(self, zone_id: str) -> list[dict]:
+        return [i for i in self.records.values() if i["zone_id"] == zone_id]
+
+    def update_record(self, record_id: str, data: dict) -> dict:
+        self.records[record_id].update(data)
+        return self.records[record_id]
+
+    def create_record(self, data: dict) -> dict:
+        record = {"id": uuid.uuid4().hex, **data}
+        self.records[record["id"]] = record
+        return record
@@ -0,0 +1,127 @@
+import unittest
+from datetime import datetime, timezone
+from unittest import mock
+
+import requests
+from hetzner_dns_api import ERROR_BODY_LIMIT, Client, InvalidResponseError, RecordAction, RecordType, retry
+
+from helpers import MockApiTestCase, StaticAdapter
+
+
+def static_client(adapter: StaticAdapter, **kwargs) -> Client:
+    sess = requests.Session()
+    sess.mount("https://", adapter)
+    return Client("token", sess, **kwargs)
+
+
+class PaginationTest(MockApiTestCase, unittest.TestCase):
+    def test_iter_pages_follows_all_pages(self):
+        zone = self.api.create_zone("example.com")
+        for i in range(5):
+            self.api.create_record({"zone_id": zone["id"], "name": f"r{i}", "type": "A", "value": f"10.0.0.{i}"})
+        client = Client("token", url=self.url)
+
+        records = list(client.iter_records(zone["id"], per_page=2))
+
+        self.assertEqual([i["name"] for i in records], ["r0", "r1", "r2", "r3", "r4"])
+        self.assertEqual({i["type"] for i in records}, {RecordType.A})
+
+    def test_get_page_reports_pagination(self):
+        for i in range(3):
+            self.api.create_zone(f"example{i}.com")
+        client = Client("token", url=self.url)
+
+        page = client.get_zones(2, per_page=2)
+
+        self.assertEqual([i["name"] for i in page.items], ["example2.com"])
+        self.assertEqual((page.page, page.last_page, page.total_entries), (2, 2, 3))
+        self.assertFalse(page.has_next_page())
+
+
+class ErrorTest(MockApiTestCase, unittest.TestCase):
+    def test_error_status_raises_http_error(self):
+        client = Client("token", url=self.url)
+
+        with self.assertRaises(requests.HTTPError) as cm:
+            client.get_record("missing")
+
+        self.assertEqual(cm.exception.response.status_code, 404)
+
+    def test_invalid_json_includes_body_snippet(self):
+        body = "<html>" + "x" * 2 * ERROR_BODY_LIMIT
+        client = static_client(StaticAdapter((200, {}, body)))
+
+        with self.assertRaises(InvalidResponseError) as cm:
+            client.get_zone("zone")
+
+        self.assertIn("status 200 to GET /zones/zone", str(cm.exception))
+        self.assertIn(repr(body[:ERROR_BODY_LIMIT] + "..."), str(cm.exception))
+
+    def test_empty_body_is_accepted(self):
+        client = static_client(StaticAdapter((200, {}, "")))
+
+        client.delete_zone("zone")
+
+
+class RetryTest(unittest.TestCase):
+    @mock.patch("hetzner_dns_api.time.sleep")
+    def test_rate_limited_requests_wait_for_retry_after(self, sleep):
+        adapter = StaticAdapter((429, {"Retry-After": "7"}, ""), (200, {}, '{"zone": {"id": "1", "name": "a.com"}}'))
+        client = static_client(adapter, middleware=[retry(attempts=3)])
+
+        self.assertEqual(client.get_zone("1")["name"], "a.com")
+        self.assertEqual(len(adapter.requests), 2)
+        sleep.assert_called_once_with(7.0)
+
+    @mock.patch("hetzner_dns_api.time.sleep")
+    def test_backoff_doubles_without_retry_after(self, sleep):
+        adapter = StaticAdapter((503, {}, ""), (503, {}, ""), (503, {}, ""))
+        client = static_client(adapter, middleware=[retry(attempts=3, backoff=0.5)])
+
+        with self.assertRaises(requests.HTTPError):
+            client.get_zone("1")
+        self.assertEqual([i.args[0] for i in sleep.call_args_list], [0.5, 1.0])
+
+    @mock.patch("hetzner_dns_api.time.sleep")
+    def test_post_is_not_retried(self, sleep):
+        adapter = StaticAdapter((429, {"Retry-After": "1"}, ""))
+        client = static_client(adapter, middleware=[retry()])
+
+        with self.assertRaises(requests.HTTPError):
+            client.create_zone("a.com")
+        self.assertEqual(len(adapter.requests), 1)
+        sleep.assert_not_called()
+
+
+class UpdateTest(MockApiTestCase, unittest.TestCase):
+    def test_ensure_record_creates_updates_and_keeps_records(self):
+        self.api.create_zone("example.com")
+        client = Client("token", url=self.url)
+
+        action, created = client.ensure_record("example.com", "www", RecordType.A, "1.2.3.4", 60)
+        self.assertEqual(action, RecordAction.CREATED)
+        action, _ = client.ensure_record("example.com", "www", RecordType.A, "1.2.3.4", 60)
+        self.assertEqual(action, RecordAction.UNCHANGED)
+        action, updated = client.ensure_record("example.com", "www", RecordType.A, "5.6.7.8", 60)
+        self.assertEqual(action, RecordAction.UPDATED)
+
+        self.assertEqual(updated["id"], created["id"])
+        self.assertEqual(self.api.records[created["id"]]["value"], "5.6.7.8")
+        self.assertEqual(len(self.api.records), 1)
+
+
+class ParseTest(unittest.TestCase):
+    def test_timestamps_are_parsed_and_unknown_formats_kept(self):
+        body = (
+            '{"record": {"id": "1", "type": "A", "created": "2019-08-24 11:31:41.442 +0000 UTC", "modified": "later"}}'
+        )
+        client = static_client(StaticAdapter((200, {}, body)))
+
+        record = client.get_record("1")
+
+        self.assertEqual(record["created"], datetime(2019, 8, 24, 11, 31, 41, 442000, timezone.utc))
+        self.assertEqual(record["modified"], "later")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,89 @@
+import tempfile
+import unittest
+from pathlib import Path
+from unittest import mock
+
+from hetzner_ddns import kritzl_dev
+from hetzner_ddns.main import process_target
+from hetzner_ddns.providers import Providers
+from hetzner_ddns.state import StateStore
+
+from helpers import MockApiTestCase
+
+TARGET = {"zone": "example.com", "record": "www"}
+
+
+class ProcessTargetTest(MockApiTestCase, unittest.TestCase):
+    def setUp(self):
+        super().setUp()
+        state_dir = tempfile.TemporaryDirectory()
+        self.addCleanup(state_dir.cleanup)
+        self.store = StateStore(Path(state_dir.name))
+        self.cfg = {"api_token": "token", "api_url": self.url}
+        zone = self.api.create_zone("example.com")
+        self.a = self.api.create_record({"zone_id": zone["id"], "name": "www", "type": "A", "value": "1.1.1.1"})
+        self.aaaa = self.api.create_record({"zone_id": zone["id"], "name": "www", "type": "AAAA", "value": "::1"})
+        self.api.create_record({"zone_id": zone["id"], "name": "other", "type": "A", "value": "1.1.1.1"})
+        self.detect(ipv4="5.5.5.5", ipv6="2001:db8::5")
+
+    def detect(self, ipv4: str, ipv6: str | Exception):
+        for name, value in (("get_ipv4", ipv4), ("get_ipv6", ipv6)):
+            patcher = mock.patch.object(
+                kritzl_dev, name, side_effect=value if isinstance(value, Exception) else None, return_value=value
+            )
+            patcher.start()
+            self.addCleanup(patcher.stop)
+
+    def process(self, updates: list[dict]):
+        process_target(self.cfg, Providers(self.cfg, self.store).for_target(TARGET), self.store, TARGET, updates)
+
+    def test_updates_records_of_the_target(self):
+        updates = []
+        self.process(updates)
+
+        self.assertEqual(
+            [(i["type"], i["old"], i["new"], i["new_ttl"]) for i in updates],
+            [("A", "1.1.1.1", "5.5.5.5", 60), ("AAAA", "::1", "2001:db8::5", 60)],
+        )
+        self.assertEqual(self.api.records[self.a["id"]]["value"], "5.5.5.5")
+        self.assertEqual(self.api.records[self.aaaa["id"]]["value"], "2001:db8::5")
+        self.assertEqual({i["value"] for i in self.api.records.values() if i["name"] == "other"}, {"1.1.1.1"})
+
+    def test_keeps_updates_before_a_failure(self):
+        self.detect(ipv4="5.5.5.5", ipv6=OSError("no ipv6"))
+        updates = []
+
+        with self.assertRaises(OSError):
+            self.process(updates)
+
+        self.assertEqual([(i["type"], i["old"], i["new"]) for i in updates], [("A", "1.1.1.1", "5.5.5.5")])
+
+    def test_retries_records_which_failed_after_others_went_through(self):
+        self.detect(ipv4="5.5.5.5", ipv6=OSError("no ipv6"))
+        updates = []
+        with self.assertRaises(OSError):
+            self.process(updates)
+        self.store.set_published(updates)
+
+        self.detect(ipv4="5.5.5.5", ipv6="2001:db8::5")
+        updates = []
+        self.process(updates)
+
+        self.assertEqual([(i["type"], i["old"], i["new"]) for i in updates[-1:]], [("AAAA", "::1", "2001:db8::5")])
+        self.assertEqual(self.api.records[self.aaaa["id"]]["value"], "2001:db8::5")
+
+    def test_skips_unchanged_addresses(self):
+        updates = []
+        self.process(updates)
+        self.store.set_published(updates)
+        self.api.records[self.a["id"]]["value"] = "1.1.1.1"
+
+        updates = []
+        self.process(updates)
+
+        self.assertEqual(updates, [])
+        self.assertEqual(self.api.records[self.a["id"]]["value"], "1.1.1.1")
+
+
+if __name__ == "__main__":
+    unittest.main()
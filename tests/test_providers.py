@@ -0,0 +1,45 @@
+import tempfile
+import unittest
+from pathlib import Path
+
+from hetzner_ddns.providers import HetznerProvider
+from hetzner_ddns.state import StateStore
+
+from helpers import FakeApi
+
+
+class HetznerProviderTest(unittest.TestCase):
+    def setUp(self):
+        state_dir = tempfile.TemporaryDirectory()
+        self.addCleanup(state_dir.cleanup)
+        self.api = FakeApi("example.com")
+        self.provider = HetznerProvider({"api_token": "token"}, StateStore(Path(state_dir.name)), self.api)
+        self.a = self.api.create_record({"zone_id": "zone", "name": "www", "type": "A", "value": "1.1.1.1"})
+        self.api.create_record({"zone_id": "zone", "name": "other", "type": "A", "value": "1.1.1.1"})
+
+    def test_find_records_returns_copies_of_matching_records(self):
+        records = self.provider.find_records("example.com", "www")
+
+        self.assertEqual(records, [self.a])
+        records[0]["value"] = "5.5.5.5"
+        self.assertEqual(self.a["value"], "1.1.1.1")
+
+    def test_update_record_sends_value_ttl_and_zone(self):
+        self.provider.update_record("example.com", {"id": self.a["id"], "name": "www", "type": "A"}, "5.5.5.5", 60)
+
+        self.assertEqual(
+            self.api.records[self.a["id"]],
+            {"id": self.a["id"], "zone_id": "zone", "name": "www", "type": "A", "value": "5.5.5.5", "ttl": 60},
+        )
+
+    def test_create_record_leaves_out_missing_ttl(self):
+        record = self.provider.create_record("example.com", "new", "AAAA", "2001:db8::5", None)
+
+        self.assertEqual(
+            self.api.records[record["id"]],
+            {"id": record["id"], "zone_id": "zone", "name": "new", "type": "AAAA", "value": "2001:db8::5"},
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()